@@ -1,15 +1,26 @@
+use std::io::Read;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
 
 use oinkie::birthmarks::{Birthmark, BirthmarkType};
 use oinkie::{OinkieError, Result};
-use oinkie::extractors::{self, Mode};
-use oinkie::comparators::{Comparator, Similarity, Type as ComparatorType};
+use oinkie::extractors::{self, Group, Mode};
+use oinkie::fuzzy::Digest;
+use oinkie::index::Index;
+use oinkie::inputs;
+use oinkie::minhash::{LshIndex, MinHasher};
+use oinkie::comparators::{Comparator, Semantics, Similarity, Type as ComparatorType};
+use oinkie::composite::{Composite, CompositeComparator, Weight};
 
 #[derive(Parser, Debug)]
 #[command(name = "oinkie", about = "A tool for extracting and comparing birthmarks from LLVM IR or BC files")]
 struct OinkieOpts {
+    #[clap(short = 'j', long, global = true, value_name = "JOBS", help = "The number of parallel jobs (default: the number of CPUs)")]
+    jobs: Option<usize>,
+
     #[clap(subcommand)]
     command: OinkieCommand,
 }
@@ -25,6 +36,12 @@ enum OinkieCommand {
     #[command(name = "run", about = "Extract birthmarks and compare them in one command")]
     Run(RunOpts),
 
+    #[command(name = "index", about = "Manage the birthmark database and search the similar birthmarks")]
+    Index(IndexOpts),
+
+    #[command(name = "composite", about = "Extract and compare the composite birthmarks of several types")]
+    Composite(CompositeOpts),
+
     #[command(name = "execute", about = "Execute the given WASM script for analyzing birthmarks")]
     Execute(ExecuteOpts),
 
@@ -34,48 +51,84 @@ enum OinkieCommand {
 
 #[derive(Parser, Debug)]
 struct ExtractSourceOpts {
-    #[clap(short = 't', long = "type", value_name = "BIRTHMARK_TYPE", default_value = "op-seq", help = "Birthmark type for extraction")]
-    btype: BirthmarkType,
+    #[clap(short = 't', long = "type", value_name = "BIRTHMARK_TYPE", value_delimiter = ',', default_value = "op-seq", help = "Birthmark types for extraction (comma-separated or repeated)")]
+    btypes: Vec<BirthmarkType>,
 
-    #[clap(short = 'm', long = "mode", value_name = "EXTRACTION_MODE", default_value = "file", help = "Extraction mode")]
-    mode: Mode,
+    #[clap(short = 'm', long = "mode", value_name = "EXTRACTION_MODE", value_delimiter = ',', default_value = "file", help = "Extraction modes (comma-separated or repeated)")]
+    modes: Vec<Mode>,
 
-    #[clap(index = 1, value_name = "IR|BC", help = "Path to the LLVM IR or BC file")]
+    #[clap(flatten)]
+    options: extractors::Options,
+
+    #[clap(flatten)]
+    filter: inputs::Filter,
+
+    #[clap(index = 1, value_name = "IR|BC|DIR|@LISTFILE|-", help = "Paths to the LLVM IR or BC files, the directories to walk recursively, the files listing the paths, or \"-\" for stdin")]
     inputs: Vec<PathBuf>,
 }
 
+impl ExtractSourceOpts {
+    /// returns all combinations of the given birthmark types and extraction modes.
+    fn combinations(&self) -> Vec<(BirthmarkType, Mode)> {
+        self.btypes.iter()
+            .flat_map(|bt| self.modes.iter().map(move |m| (bt.clone(), m.clone())))
+            .collect()
+    }
+}
+
 #[derive(Parser, Debug)]
 struct ExtractOpts {
     #[clap(short, long, default_value = "-", value_name = "DEST", help = "Output file path (default: stdout (\"-\"))")]
     dest: String,
 
+    #[clap(long, help = "Output the fuzzy hash digests instead of the birthmarks")]
+    digest: bool,
+
     #[clap(flatten)]
     source: ExtractSourceOpts,
 }
 
-fn extract_birthmarks(inputs: Vec<PathBuf>, btype: BirthmarkType, mode: &Mode) -> Result<Vec<Birthmark>> {
-    let result = inputs.iter()
-        .map(|p| extractors::from_path(p, &btype, mode))
+/// extracts the birthmarks of all combinations of the types and the modes, parsing each input once,
+/// and groups them by the combinations across the inputs.
+fn extract_birthmarks(opts: &ExtractSourceOpts, jobs: usize) -> Result<Vec<Group>> {
+    let types = opts.combinations();
+    let result = parallel_map(&inputs::expand(&opts.inputs, &opts.filter)?, jobs, |p| if inputs::is_stdin(p) {
+        read_stdin().and_then(|bytes| extractors::from_bytes_many(&bytes, &types, &opts.options))
+    } else {
+        extractors::from_path_many(p, &types, &opts.options)
+    });
+    let mut groups = types.into_iter()
+        .map(|(btype, mode)| Group { btype, mode, birthmarks: vec![] })
         .collect::<Vec<_>>();
-    OinkieError::vec_result_to_result_vec(result)
-        .map(|v| v.into_iter().flatten().collect())
+    for input in OinkieError::vec_result_to_result_vec(result)? {
+        groups.iter_mut().zip(input)
+            .for_each(|(group, g)| group.birthmarks.extend(g.birthmarks));
+    }
+    Ok(groups)
 }
 
-fn extract(opts: ExtractOpts) -> oinkie::Result<()> {
+/// reads the IR/BC from the standard input, given as `-` in the inputs.
+fn read_stdin() -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match std::io::stdin().lock().read_to_end(&mut bytes) {
+        Ok(_) => Ok(bytes),
+        Err(e) => Err(OinkieError::Io(e)),
+    }
+}
+
+fn extract(opts: ExtractOpts, jobs: usize) -> oinkie::Result<()> {
     let mut errs = vec![];
-    let (btype, dest, inputs, mode) = (opts.source.btype, opts.dest, opts.source.inputs, opts.source.mode);
-
-    match extract_birthmarks(inputs, btype, &mode) {
-        Ok(birthmarks) => {
-            let json = serde_json::to_string_pretty(&birthmarks)
-                .map_err(OinkieError::Json)?;
-            if dest == "-" {
-                println!("{}", json);
-            } else {
-                std::fs::write(dest, json)
-                    .map_err(OinkieError::Io)?;
-            }
+    let dest = opts.dest;
+
+    match extract_birthmarks(&opts.source, jobs) {
+        Ok(groups) if opts.digest => {
+            let digests = groups.iter()
+                .flat_map(|g| g.birthmarks.iter().map(Digest::new))
+                .collect::<Vec<_>>();
+            output_json(&digests, dest)?
         },
+        Ok(mut groups) if groups.len() == 1 => output_json(&groups.remove(0).birthmarks, dest)?,
+        Ok(groups) => output_json(&groups, dest)?,
         Err(e) => errs.push(e),
     }
     OinkieError::error_or((), errs)
@@ -86,8 +139,31 @@ struct CompareAlgorithmsOpts {
     #[clap(short, long, default_value = "jaccard", value_name = "COMPARATOR_TYPE", help = "Specifies the comparator")]
     comparator: ComparatorType,
 
+    #[clap(short, long, default_value = "set", value_name = "SEMANTICS", help = "Specifies how simpson, jaccard, and dice count the duplicated elements")]
+    semantics: Semantics,
+
     #[clap(short, long, default_value = "-", value_name = "DEST", help = "Output file path (default: stdout (\"-\"))")]
     dest: String,
+
+    #[clap(flatten)]
+    candidates: CandidateOpts,
+}
+
+#[derive(Parser, Debug)]
+struct CandidateOpts {
+    #[clap(long = "lsh-threshold", value_name = "THRESHOLD", value_parser = parse_threshold, help = "Compare only the candidate pairs whose Jaccard index estimated by MinHash is above the threshold (0.0 to 1.0)")]
+    threshold: Option<f64>,
+
+    #[clap(long = "num-hashes", value_name = "NUM", default_value_t = 128, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "The number of hash functions for MinHash signatures")]
+    num_hashes: usize,
+}
+
+fn parse_threshold(s: &str) -> std::result::Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+        Ok(_) => Err("the threshold must be in the range of 0.0 to 1.0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -95,6 +171,9 @@ struct CompareOpts {
     #[clap(flatten)]
     algorithm: CompareAlgorithmsOpts,
 
+    #[clap(long, conflicts_with_all = ["comparator", "semantics", "threshold", "num_hashes"], help = "The given files are the fuzzy hash digests (the results of `extract --digest`)")]
+    digests: bool,
+
     #[clap(index = 1, help = "Paths of the birthmark files to compare")]
     birthmarks: Vec<PathBuf>,
 }
@@ -108,6 +187,176 @@ struct RunOpts {
     compare_opts: CompareAlgorithmsOpts,
 }
 
+#[derive(Parser, Debug)]
+struct IndexOpts {
+    #[clap(short, long, default_value = "oinkie-index.json", value_name = "INDEX", help = "Path of the index file")]
+    index: PathBuf,
+
+    #[clap(subcommand)]
+    command: IndexCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    #[command(name = "add", about = "Add the birthmarks of IR/BC files or birthmark files into the index")]
+    Add(ExtractSourceOpts),
+
+    #[command(name = "query", about = "Search the stored birthmarks similar to the given ones")]
+    Query(IndexQueryOpts),
+
+    #[command(name = "list", about = "List the stored birthmarks")]
+    List,
+
+    #[command(name = "remove", about = "Remove the stored birthmarks of the given names")]
+    Remove(IndexRemoveOpts),
+}
+
+#[derive(Parser, Debug)]
+struct IndexQueryOpts {
+    #[clap(short = 'k', long = "top", default_value_t = 10, value_name = "K", help = "The number of results for each query")]
+    top: usize,
+
+    #[clap(short, long, default_value = "-", value_name = "DEST", help = "Output file path (default: stdout (\"-\"))")]
+    dest: String,
+
+    #[clap(flatten)]
+    source: ExtractSourceOpts,
+}
+
+#[derive(Parser, Debug)]
+struct IndexRemoveOpts {
+    #[clap(index = 1, value_name = "NAMES", help = "Names of the stored birthmarks")]
+    names: Vec<String>,
+}
+
+/// loads the birthmark files (`.json`) and extracts the birthmarks from the other inputs, in the groups of the birthmark types.
+fn load_or_extract_birthmarks(opts: ExtractSourceOpts, jobs: usize) -> Result<Vec<Group>> {
+    let types = opts.combinations();
+    let result = parallel_map(&inputs::expand(&opts.inputs, &opts.filter)?, jobs, |p| match p.extension().and_then(|e| e.to_str()) {
+        Some("json") => oinkie::birthmarks::load_groups(p),
+        _ if inputs::is_stdin(p) => read_stdin()
+            .and_then(|bytes| extractors::from_bytes_many(&bytes, &types, &opts.options)),
+        _ => extractors::from_path_many(p, &types, &opts.options),
+    });
+    OinkieError::vec_result_to_result_vec(result)
+        .map(|v| extractors::merge_groups(v.into_iter().flatten()))
+}
+
+fn index(opts: IndexOpts, jobs: usize) -> oinkie::Result<()> {
+    let mut index = Index::open(&opts.index)?;
+    match opts.command {
+        IndexCommand::Add(source) => {
+            let birthmarks = load_or_extract_birthmarks(source, jobs)?.into_iter()
+                .flat_map(|g| g.birthmarks)
+                .collect::<Vec<_>>();
+            index.add_all(&birthmarks);
+            index.save(&opts.index)
+        },
+        IndexCommand::Query(qopts) => {
+            let groups = load_or_extract_birthmarks(qopts.source, jobs)?;
+            let hits = groups.iter()
+                .flat_map(|g| g.birthmarks.iter())
+                .flat_map(|b| index.query(b, qopts.top))
+                .collect::<Vec<_>>();
+            output_json(&hits, qopts.dest)
+        },
+        IndexCommand::List => {
+            let infos = index.list().collect::<Vec<_>>();
+            output_json(&infos, "-".to_string())
+        },
+        IndexCommand::Remove(ropts) => {
+            let mut errs = vec![];
+            for name in ropts.names {
+                if index.remove(&name) == 0 {
+                    errs.push(OinkieError::Fatal(format!("{}: not found in the index", name)));
+                }
+            }
+            index.save(&opts.index)?;
+            OinkieError::error_or((), errs)
+        },
+    }
+}
+
+#[derive(Parser, Debug)]
+struct CompositeOpts {
+    #[clap(subcommand)]
+    command: CompositeCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CompositeCommand {
+    #[command(name = "extract", about = "Extract the birthmarks of several types in a single pass into the composite birthmarks")]
+    Extract(CompositeExtractOpts),
+
+    #[command(name = "compare", about = "Compare the composite birthmarks with the weighted similarities of their types")]
+    Compare(CompositeCompareOpts),
+}
+
+#[derive(Parser, Debug)]
+struct CompositeExtractOpts {
+    #[clap(short = 't', long = "type", value_name = "BIRTHMARK_TYPE", required = true, value_delimiter = ',', help = "Birthmark types for extraction")]
+    btypes: Vec<BirthmarkType>,
+
+    #[clap(short = 'm', long = "mode", value_name = "EXTRACTION_MODE", default_value = "file", help = "Extraction mode")]
+    mode: Mode,
+
+    #[clap(flatten)]
+    options: extractors::Options,
+
+    #[clap(flatten)]
+    filter: inputs::Filter,
+
+    #[clap(short, long, default_value = "-", value_name = "DEST", help = "Output file path (default: stdout (\"-\"))")]
+    dest: String,
+
+    #[clap(index = 1, value_name = "IR|BC|DIR|@LISTFILE|-", help = "Paths to the LLVM IR or BC files, the directories to walk recursively, the files listing the paths, or \"-\" for stdin")]
+    inputs: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct CompositeCompareOpts {
+    #[clap(short, long = "weight", value_name = "BIRTHMARK_TYPE:COMPARATOR[:WEIGHT]", required = true, help = "Comparator and weight of each birthmark type, e.g., op-seq:lcs:2")]
+    weights: Vec<Weight>,
+
+    #[clap(short, long, default_value = "set", value_name = "SEMANTICS", help = "Specifies how simpson, jaccard, and dice count the duplicated elements")]
+    semantics: Semantics,
+
+    #[clap(short, long, default_value = "-", value_name = "DEST", help = "Output file path (default: stdout (\"-\"))")]
+    dest: String,
+
+    #[clap(index = 1, value_name = "COMPOSITE_FILES", help = "The composite birthmark files")]
+    composites: Vec<PathBuf>,
+}
+
+fn composite(opts: CompositeOpts, jobs: usize) -> oinkie::Result<()> {
+    match opts.command {
+        CompositeCommand::Extract(eopts) => {
+            let result = parallel_map(&inputs::expand(&eopts.inputs, &eopts.filter)?, jobs, |p| if inputs::is_stdin(p) {
+                read_stdin().and_then(|bytes| oinkie::composite::from_bytes(&bytes, &eopts.btypes, &eopts.mode, &eopts.options))
+            } else {
+                oinkie::composite::from_path(p, &eopts.btypes, &eopts.mode, &eopts.options)
+            });
+            let composites = OinkieError::vec_result_to_result_vec(result)?
+                .into_iter().flatten().collect::<Vec<_>>();
+            output_json(&composites, eopts.dest)
+        },
+        CompositeCommand::Compare(copts) => {
+            let result = copts.composites.iter()
+                .map(oinkie::composite::load)
+                .collect::<Vec<_>>();
+            let composites: Vec<Composite> = OinkieError::vec_result_to_result_vec(result)?
+                .into_iter().flatten().collect();
+            if composites.len() < 2 {
+                return Err(OinkieError::Fatal("At least two composite birthmarks are required for comparison".to_string()));
+            }
+            let comparator = CompositeComparator::new(copts.weights, &copts.semantics);
+            let result = parallel_map(&all_pairs(composites.len()), jobs,
+                |&(i, j)| comparator.compare(&composites[i], &composites[j]));
+            output_json(&OinkieError::vec_result_to_result_vec(result)?, copts.dest)
+        },
+    }
+}
+
 #[derive(Parser, Debug)]
 struct ExecuteOpts {
     #[clap(index = 1, default_value = "-", help = "the script file. If absent or '-', read from stdin.")]
@@ -122,16 +371,52 @@ fn read_birthmarks_from_json(paths: Vec<PathBuf>) -> Result<Vec<Birthmark>> {
         .map(|v| v.into_iter().flatten().collect())
 }
 
-fn read_and_compare(opts: CompareOpts) -> oinkie::Result<()> {
+fn read_and_compare(opts: CompareOpts, jobs: usize) -> oinkie::Result<()> {
     let (paths, algorithm) = (opts.birthmarks, opts.algorithm);
-    let birthmarks = read_birthmarks_from_json(paths);
-    compare(birthmarks, algorithm)
+    if opts.digests {
+        let digests = read_digests_from_json(paths)?;
+        output_similarities(calculate_digest_similarities(digests, jobs)?, algorithm.dest)
+    } else {
+        let birthmarks = read_birthmarks_from_json(paths);
+        compare(birthmarks, algorithm, jobs)
+    }
+}
+
+fn read_digests_from_json(paths: Vec<PathBuf>) -> Result<Vec<Digest>> {
+    let result = paths.iter()
+        .map(oinkie::fuzzy::load)
+        .collect::<Vec<_>>();
+    OinkieError::vec_result_to_result_vec(result)
+        .map(|v| v.into_iter().flatten().collect())
+}
+
+fn calculate_digest_similarities(digests: Vec<Digest>, jobs: usize) -> Result<Vec<Similarity>> {
+    if digests.len() < 2 {
+        return Err(OinkieError::Fatal("At least two digests are required for comparison".to_string()));
+    }
+    let results = parallel_map(&all_pairs(digests.len()), jobs, |&(i, j)| {
+        let (a, b) = (&digests[i], &digests[j]);
+        if !a.info.is_same_type(&b.info) {
+            return Err(OinkieError::Fatal(format!("Birthmark types do not match: {:?} vs {:?}", a.info, b.info)));
+        }
+        let start = std::time::Instant::now();
+        let score = a.similarity(b);
+        Ok(Similarity {
+            btype: a.info.btype.clone(),
+            a_info: a.info.clone(),
+            b_info: b.info.clone(),
+            ctype: ComparatorType::FuzzyHash,
+            score,
+            elapsed_ms: Some(start.elapsed().as_secs_f64() * 1000.0),
+        })
+    });
+    OinkieError::vec_result_to_result_vec(results)
 }
 
-fn compare(birthmarks: Result<Vec<Birthmark>>, opts: CompareAlgorithmsOpts) -> oinkie::Result<()> {
-    let comparator = oinkie::comparators::comparator(&opts.comparator);
+fn compare(birthmarks: Result<Vec<Birthmark>>, opts: CompareAlgorithmsOpts, jobs: usize) -> oinkie::Result<()> {
+    let comparator = oinkie::comparators::comparator_with(&opts.comparator, &opts.semantics);
     match birthmarks {
-        Ok(birthmarks) => match calculate_similarities(birthmarks, comparator) {
+        Ok(birthmarks) => match calculate_similarities(birthmarks, comparator.as_ref(), &opts.candidates, jobs) {
             Ok(similarities) => {
                 output_similarities(similarities, opts.dest)
             },
@@ -142,7 +427,11 @@ fn compare(birthmarks: Result<Vec<Birthmark>>, opts: CompareAlgorithmsOpts) -> o
 }
 
 fn output_similarities(similarities: Vec<Similarity>, dest: String) -> oinkie::Result<()> {
-    let json = serde_json::to_string_pretty(&similarities)
+    output_json(&similarities, dest)
+}
+
+fn output_json<T: Serialize>(items: &T, dest: String) -> oinkie::Result<()> {
+    let json = serde_json::to_string_pretty(items)
         .map_err(OinkieError::Json)?;
     if dest == "-" {
         println!("{}", json);
@@ -153,39 +442,84 @@ fn output_similarities(similarities: Vec<Similarity>, dest: String) -> oinkie::R
     Ok(())
 }
 
-fn calculate_similarities(birthmarks: Vec<Birthmark>, comparator: Box<dyn Comparator>) -> Result<Vec<Similarity>> {
+fn calculate_similarities(birthmarks: Vec<Birthmark>, comparator: &dyn Comparator, opts: &CandidateOpts, jobs: usize) -> Result<Vec<Similarity>> {
     if birthmarks.len() < 2 {
         Err(OinkieError::Fatal("At least two birthmarks are required for comparison".to_string()))
     } else {
-        let mut errs = vec![];
-        let mut oks = vec![];
-        for i in 0..birthmarks.len() {
-            for j in (i+1)..birthmarks.len() {
-                let a = &birthmarks[i];
-                let b = &birthmarks[j];
-                if !a.is_same_type(b) {
-                    errs.push(OinkieError::Fatal(format!("Birthmark types do not match: {:?} vs {:?}", a.info, b.info)));
-                }
-                match comparator.compare(a, b) {
-                    Ok(similarity) => oks.push(similarity),
-                    Err(e) => errs.push(e),
-                }
+        let results = parallel_map(&find_pairs(&birthmarks, opts)?, jobs, |&(i, j)| {
+            let a = &birthmarks[i];
+            let b = &birthmarks[j];
+            if !a.is_same_type(b) {
+                return Err(OinkieError::Fatal(format!("Birthmark types do not match: {:?} vs {:?}", a.info, b.info)));
             }
-        }
-        OinkieError::error_or(oks, errs)
+            comparator.compare(a, b)
+        });
+        OinkieError::vec_result_to_result_vec(results)
     }
 }
 
-fn run(opts: RunOpts) -> oinkie::Result<()> {
-    let (eopts, copts) = (opts.extract_opts, opts.compare_opts);
-    let birthmarks = extract_birthmarks(eopts.inputs, eopts.btype, &eopts.mode);
-    let (ctype, dest) = (copts.comparator, copts.dest);
-    let comparator = oinkie::comparators::comparator(&ctype);
-    match birthmarks {
-        Ok(birthmarks) => match calculate_similarities(birthmarks, comparator) {
-            Ok(similarities) => output_similarities(similarities, dest),
-            Err(e) => Err(e),
+/// applies the function to the items on the given number of threads,
+/// and returns the results in the order of the items regardless of the scheduling.
+fn parallel_map<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|s| {
+        let handles = (0..jobs)
+            .map(|_| s.spawn(|| {
+                let mut results = vec![];
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    match items.get(i) {
+                        Some(item) => results.push((i, f(item))),
+                        None => break results,
+                    }
+                }
+            }))
+            .collect::<Vec<_>>();
+        handles.into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+fn all_pairs(len: usize) -> Vec<(usize, usize)> {
+    (0..len)
+        .flat_map(|i| ((i + 1)..len).map(move |j| (i, j)))
+        .collect()
+}
+
+/// returns all pairs of the birthmarks, or the candidate pairs found by MinHash and LSH if the threshold is given.
+fn find_pairs(birthmarks: &[Birthmark], opts: &CandidateOpts) -> oinkie::Result<Vec<(usize, usize)>> {
+    match opts.threshold {
+        None => Ok(all_pairs(birthmarks.len())),
+        Some(threshold) => {
+            let hasher = MinHasher::new(opts.num_hashes, 0);
+            let mut index = LshIndex::with_threshold(opts.num_hashes, threshold)?;
+            birthmarks.iter().for_each(|b| { index.insert(hasher.signature(b)); });
+            let signatures = index.signatures();
+            Ok(index.candidates().into_iter()
+                .filter(|(i, j)| signatures[*i].estimate(&signatures[*j]) >= threshold)
+                .collect())
         },
+    }
+}
+
+/// compares the birthmarks within each group of the birthmark type and the extraction mode.
+fn run(opts: RunOpts, jobs: usize) -> oinkie::Result<()> {
+    let (eopts, copts) = (opts.extract_opts, opts.compare_opts);
+    let groups = extract_birthmarks(&eopts, jobs)?;
+    let (ctype, semantics, dest) = (copts.comparator, copts.semantics, copts.dest);
+    let comparator = oinkie::comparators::comparator_with(&ctype, &semantics);
+    let result = groups.into_iter()
+        .map(|g| calculate_similarities(g.birthmarks, comparator.as_ref(), &copts.candidates, jobs))
+        .collect::<Vec<_>>();
+    match OinkieError::vec_result_to_result_vec(result) {
+        Ok(similarities) => output_similarities(similarities.into_iter().flatten().collect(), dest),
         Err(e) => Err(e),
     }
 }
@@ -213,10 +547,14 @@ Oinkie extracts birthmarks from given IR/BC codes and compares them to calculate
 
 fn perform(opts: OinkieOpts) -> oinkie::Result<()> {
     use OinkieCommand::*;
+    let jobs = opts.jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
     match opts.command {
-        Extract(opts) => extract(opts),
-        Compare(opts) => read_and_compare(opts),
-        Run(opts) => run(opts),
+        Extract(opts) => extract(opts, jobs),
+        Compare(opts) => read_and_compare(opts, jobs),
+        Run(opts) => run(opts, jobs),
+        Index(opts) => index(opts, jobs),
+        Composite(opts) => composite(opts, jobs),
         Execute(opts) => execute(opts),
         Info => info(),
     }
@@ -225,3 +563,27 @@ fn perform(opts: OinkieOpts) -> oinkie::Result<()> {
 fn main() -> Result<()>{
     perform(OinkieOpts::parse())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_parallel_map() {
+        let items = (0..16).collect::<Vec<u64>>();
+        let threads = Mutex::new(HashSet::new());
+        let results = parallel_map(&items, 4, |&i| {
+            // the earlier items take longer, so that they finish after the later ones.
+            std::thread::sleep(Duration::from_millis((16 - i) * 3));
+            threads.lock().unwrap().insert(std::thread::current().id());
+            i * 10
+        });
+        assert_eq!(results, items.iter().map(|i| i * 10).collect::<Vec<_>>());
+        assert!(threads.into_inner().unwrap().len() > 1);
+        assert!(parallel_map(&[] as &[u64], 4, |&i| i).is_empty());
+    }
+}
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use clap::ValueEnum;
 use serde::{Serialize, Deserialize};
 
-use crate::extractors::Mode;
+use crate::extractors::{merge_groups, Abstraction, Boundary, Group, Mode, NameForm};
 use crate::{OinkieError, Result};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -19,19 +19,55 @@ pub struct Info {
     pub path: PathBuf,
     pub btype: BirthmarkType,
     pub mode: Mode,
+    /// the length of the k-grams of the birthmark types with the arbitrary k, such as [`BirthmarkType::KGram`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    /// the maximum number of the skipped elements of [`BirthmarkType::SkipGram`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip: Option<usize>,
+    /// the form of the function names of the birthmark types of the function calls, such as [`BirthmarkType::Sfc`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub names: Option<NameForm>,
+    /// the abstraction level of the instruction labels of the opcode birthmark types, such as [`BirthmarkType::OpSeq`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abstraction: Option<Abstraction>,
+    /// the boundaries of the windows of the k-gram birthmark types, such as [`BirthmarkType::KGram`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boundary: Option<Boundary>,
 }
 
 impl Info {
     pub fn new(name: String, path: PathBuf, btype: BirthmarkType, mode: Mode) -> Self {
-        Self { name, path, btype, mode }
+        Self { name, path, btype, mode, k: None, skip: None, names: None, abstraction: None, boundary: None }
     }
 
     pub fn new_from(&self, name: String) -> Self {
-        Self { name, path: self.path.clone(), btype: self.btype.clone(), mode: self.mode.clone() }
+        Self { name, path: self.path.clone(), btype: self.btype.clone(), mode: self.mode.clone(), k: self.k, skip: self.skip, names: self.names, abstraction: self.abstraction, boundary: self.boundary }
+    }
+
+    pub fn with_k(self, k: Option<usize>) -> Self {
+        Self { k, ..self }
+    }
+
+    pub fn with_skip(self, skip: Option<usize>) -> Self {
+        Self { skip, ..self }
+    }
+
+    pub fn with_names(self, names: Option<NameForm>) -> Self {
+        Self { names, ..self }
+    }
+
+    pub fn with_abstraction(self, abstraction: Option<Abstraction>) -> Self {
+        Self { abstraction, ..self }
+    }
+
+    pub fn with_boundary(self, boundary: Option<Boundary>) -> Self {
+        Self { boundary, ..self }
     }
 
     pub fn is_same_type(&self, other: &Info) -> bool {
-        self.btype == other.btype && self.mode == other.mode
+        self.btype == other.btype && self.mode == other.mode && self.k == other.k && self.skip == other.skip
+            && self.names == other.names && self.abstraction == other.abstraction && self.boundary == other.boundary
     }
 }
 
@@ -63,12 +99,48 @@ pub enum BirthmarkType {
     HeptaGram,
     #[clap(help = "octa-grams of opcodes (8-gram)")]
     OctaGram,
+    #[clap(help = "k-grams of opcodes with the arbitrary k (--kgram-size)")]
+    KGram,
+    #[clap(help = "k-grams of function calls (callee names) with the arbitrary k (--kgram-size)")]
+    CallKGram,
+    #[clap(help = "frequencies of skip-grams of opcodes, k opcodes with up to s skipped ones (--kgram-size, --skip-size)")]
+    SkipGram,
+    #[clap(help = "caller-callee edges of function calls (call graph)")]
+    CallGraph,
+    #[clap(help = "graph invariants of control flow graphs of functions")]
+    CfgInv,
+    #[clap(help = "Weisfeiler-Lehman hashes of control flow graphs of functions")]
+    CfgHash,
+    #[clap(help = "Weisfeiler-Lehman subtree features of basic blocks labeled by their opcodes")]
+    CfgWl,
+    #[clap(help = "sequence of constant values in operands and global initializers (CVFV)")]
+    Cvfv,
+    #[clap(help = "strings and constant tables in global variables")]
+    GlobalData,
+}
+
+/// The content of the birthmark file, the list of the birthmarks,
+/// or the groups of them written by `extract` with the multiple types or modes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Birthmarks(Vec<Birthmark>),
+    Groups(Vec<Group>),
 }
 
+/// loads the birthmarks in the file, flattening the groups.
 pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Birthmark>> {
+    load_groups(path).map(|groups| groups.into_iter().flat_map(|g| g.birthmarks).collect())
+}
+
+/// loads the birthmarks in the file as the groups of the birthmark types and the modes.
+/// The list of the birthmarks is split into the groups in the order of their first appearances.
+pub fn load_groups<P: AsRef<Path>>(path: P) -> Result<Vec<Group>> {
     match std::fs::File::open(path.as_ref()) {
-        Ok(file) => match serde_json::from_reader(file) {
-            Ok(birthmark) => Ok(birthmark),
+        Ok(file) => match serde_json::from_reader(std::io::BufReader::new(file)) {
+            Ok(Stored::Groups(groups)) => Ok(groups),
+            Ok(Stored::Birthmarks(birthmarks)) => Ok(merge_groups(birthmarks.into_iter()
+                .map(|b| Group { btype: b.info.btype.clone(), mode: b.info.mode.clone(), birthmarks: vec![b] }))),
             Err(e) => Err(OinkieError::Json(e)),
         },
         Err(e) => Err(OinkieError::Io(e)),
@@ -101,6 +173,15 @@ impl Birthmark {
         to_freq_elements(&self.elements)
     }
 
+    /// returns the total weights of the elements for each key.
+    pub fn weights(&self) -> HashMap<Key<'_>, usize> {
+        let mut map = HashMap::new();
+        for e in &self.elements {
+            *map.entry(e.key()).or_insert(0) += e.weight();
+        }
+        map
+    }
+
     pub fn is_same_type(&self, other: &Birthmark) -> bool {
         self.info.is_same_type(&other.info)
     }
@@ -115,12 +196,88 @@ pub enum Element {
     Freq(usize, String),
 }
 
+/// The identity of an [`Element`] used to pair up the elements of two birthmarks.
+/// [`Element::Freq`] is identified by its name only, and the count is treated as its weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key<'a> {
+    Element(&'a Element),
+    Name(&'a str),
+}
+
+impl Key<'_> {
+    /// returns the FNV-1a hash of the key, which is stable across the platforms and the builds.
+    pub fn stable_hash(&self) -> u64 {
+        let mut h = Fnv::new();
+        match self {
+            Key::Element(Element::Str(s)) => { h.write(&[0]); h.write(s.as_bytes()) },
+            Key::Element(Element::Int(i)) => { h.write(&[1]); h.write(&i.to_le_bytes()) },
+            Key::Element(Element::Kgram(items)) => {
+                h.write(&[2]);
+                for item in items {
+                    h.write(item.as_bytes());
+                    h.write(&[0xff]);
+                }
+            },
+            Key::Element(Element::Freq(_, name)) => { h.write(&[3]); h.write(name.as_bytes()) },
+            Key::Name(name) => { h.write(&[3]); h.write(name.as_bytes()) },
+        }
+        h.finish()
+    }
+}
+
+/// FNV-1a hasher, which is stable across the platforms and the builds, unlike [`std::hash::DefaultHasher`].
+pub(crate) struct Fnv(u64);
+
+impl Fnv {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 impl Element {
+    pub fn key(&self) -> Key<'_> {
+        match self {
+            Element::Freq(_, name) => Key::Name(name),
+            _ => Key::Element(self),
+        }
+    }
+
+    /// returns the count of [`Element::Freq`], and 1 for the other variants.
+    pub fn weight(&self) -> usize {
+        match self {
+            Element::Freq(count, _) => *count,
+            _ => 1,
+        }
+    }
+
+    /// returns `true` if both elements have the same key and the same weight.
     pub fn is_same(&self, other: &Element) -> bool {
-        match (self, other) {
-            (Element::Str(a), Element::Str(b)) => a == b,
-            (Element::Int(a), Element::Int(b)) => a == b,
-            _ => false,
+        self.key() == other.key() && self.weight() == other.weight()
+    }
+
+    /// returns the similarity of two elements in `[0, 1]`.
+    /// The elements with the different keys are not similar (0.0),
+    /// otherwise the ratio of the smaller weight to the larger one (1.0 if [`Element::is_same`]).
+    pub fn similarity(&self, other: &Element) -> f64 {
+        if self.key() != other.key() {
+            0.0
+        } else {
+            let (w1, w2) = (self.weight(), other.weight());
+            match w1.max(w2) {
+                0 => 1.0,
+                max => w1.min(w2) as f64 / max as f64,
+            }
         }
     }
 }
@@ -132,3 +289,43 @@ fn to_freq_elements(elements: &Vec<Element>) -> HashMap<&Element, usize> {
         }
         map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_equality() {
+        let k1 = Element::Kgram(vec!["Add".into(), "Sub".into()]);
+        let k2 = Element::Kgram(vec!["Add".into(), "Sub".into()]);
+        let k3 = Element::Kgram(vec!["Sub".into(), "Add".into()]);
+        assert!(k1.is_same(&k2));
+        assert!(!k1.is_same(&k3));
+        assert_eq!(k1.similarity(&k3), 0.0);
+
+        let f1 = Element::Freq(3, "Add".into());
+        let f2 = Element::Freq(4, "Add".into());
+        assert!(f1.is_same(&Element::Freq(3, "Add".into())));
+        assert!(!f1.is_same(&f2));
+        assert_eq!(f1.similarity(&f2), 0.75);
+        assert_eq!(f1.similarity(&Element::Freq(3, "Sub".into())), 0.0);
+        assert_eq!(f1.similarity(&Element::Str("Add".into())), 0.0);
+    }
+
+    #[test]
+    fn test_load_groups() {
+        let birthmark = |btype| Birthmark::new(Info::new("a".into(), PathBuf::from("a.ll"), btype, Mode::File), vec![Element::Str("Add".into())]);
+        let kgram = |k| Birthmark::new(birthmark(BirthmarkType::KGram).info.with_k(Some(k)), vec![]);
+        let birthmarks = vec![birthmark(BirthmarkType::OpSeq), kgram(2), birthmark(BirthmarkType::OpSet), kgram(3), birthmark(BirthmarkType::OpSeq), kgram(2)];
+        let path = std::env::temp_dir().join(format!("oinkie-birthmarks-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&birthmarks).unwrap()).unwrap();
+        let groups = load_groups(&path).unwrap();
+        assert_eq!(groups.iter().map(|g| (g.btype.clone(), g.birthmarks.len())).collect::<Vec<_>>(),
+            [(BirthmarkType::OpSeq, 2), (BirthmarkType::KGram, 2), (BirthmarkType::OpSet, 1), (BirthmarkType::KGram, 1)]);
+
+        std::fs::write(&path, serde_json::to_string(&groups).unwrap()).unwrap();
+        assert_eq!(load_groups(&path).unwrap().len(), 4);
+        assert_eq!(load(&path).unwrap().len(), 6);
+        std::fs::remove_file(path).unwrap();
+    }
+}
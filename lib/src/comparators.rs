@@ -1,11 +1,14 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::iter::zip;
 use clap::{Parser, ValueEnum};
 use serde::{Serialize, Deserialize};
 
-use crate::birthmarks::{Birthmark, BirthmarkType, Element, Info};
-use crate::Result;
+use crate::birthmarks::{Birthmark, BirthmarkType, Element, Info, Key};
+use crate::fuzzy::Digest;
+use crate::graphs;
+use crate::{OinkieError, Result};
+
+mod sequences;
 
 #[derive(Serialize, Deserialize, Parser, Debug, Clone, PartialEq, Eq, Hash, ValueEnum)]
 pub enum Type {
@@ -17,12 +20,35 @@ pub enum Type {
     Dice,
     /// Cosine similarity
     Cosine,
+    /// Weighted Jaccard index (sum of minimum counts over sum of maximum counts)
+    WeightedJaccard,
+    /// Weighted Dice's coefficient (twice the sum of minimum counts over sum of all counts)
+    WeightedDice,
+    /// Bray-Curtis similarity (1 - Bray-Curtis dissimilarity), an alias of [`Type::WeightedDice`] for the counts
+    BrayCurtis,
+    /// Similarity of the fuzzy hashes (context triggered piecewise hashes) of element streams
+    FuzzyHash,
+    /// Weisfeiler-Lehman kernel of the graphs of edge elements (e.g., call-graph)
+    WlKernel,
+    /// Mean of the edge-set Jaccard index and the Weisfeiler-Lehman kernel
+    Graph,
     /// Longest common subsequence
     LCS,
     /// Levenshtein distance (Edit distance)
     Levenshtein,
 }
 
+/// How the set-based comparators ([`Type::Simpson`], [`Type::Jaccard`] and [`Type::Dice`])
+/// treat the elements of the birthmarks.
+#[derive(Serialize, Deserialize, Parser, Debug, Clone, PartialEq, Eq, Hash, ValueEnum, Default)]
+pub enum Semantics {
+    /// duplicated elements are counted once.
+    #[default]
+    Set,
+    /// duplicated elements are counted as many times as they appear.
+    Multiset,
+}
+
 impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -30,11 +56,21 @@ impl Display for Type {
 }
 
 pub fn comparator(t: &Type) -> Box<dyn Comparator> {
+    comparator_with(t, &Semantics::default())
+}
+
+pub fn comparator_with(t: &Type, semantics: &Semantics) -> Box<dyn Comparator> {
     match t {
-        Type::Simpson => Box::new(Simpson{}),
-        Type::Jaccard => Box::new(Jaccard{}),
-        Type::Dice => Box::new(Dice{}),
+        Type::Simpson => Box::new(Simpson{ semantics: semantics.clone() }),
+        Type::Jaccard => Box::new(Jaccard{ semantics: semantics.clone() }),
+        Type::Dice => Box::new(Dice{ semantics: semantics.clone() }),
         Type::Cosine => Box::new(Cosine{}),
+        Type::WeightedJaccard => Box::new(WeightedJaccard{}),
+        Type::WeightedDice => Box::new(WeightedDice{}),
+        Type::BrayCurtis => Box::new(BrayCurtis{}),
+        Type::FuzzyHash => Box::new(FuzzyHash{}),
+        Type::WlKernel => Box::new(WlKernel{}),
+        Type::Graph => Box::new(Graph{}),
         Type::LCS => Box::new(LCS{}),
         Type::Levenshtein => Box::new(Levenshtein{}),
     }
@@ -50,7 +86,7 @@ pub struct Similarity {
     pub elapsed_ms: Option<f64>,
 }
 
-pub trait Comparator {
+pub trait Comparator: Send + Sync {
     fn name(&self) -> String {
         self.ctype().to_string()
     }
@@ -79,17 +115,38 @@ pub trait Comparator {
 }
 
 struct Simpson {
+    semantics: Semantics,
 }
 
 struct Jaccard {
+    semantics: Semantics,
 }
 
 struct Dice {
+    semantics: Semantics,
 }
 
 struct Cosine {
 }
 
+struct WeightedJaccard {
+}
+
+struct WeightedDice {
+}
+
+struct BrayCurtis {
+}
+
+struct FuzzyHash {
+}
+
+struct WlKernel {
+}
+
+struct Graph {
+}
+
 struct LCS {
 }
 
@@ -102,10 +159,8 @@ impl Comparator for Simpson {
     }
 
     fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
-        Ok(zip(a.iter(), b.iter())
-                .filter(|(a, b)| a.is_same(b))
-                .count() as f64
-                / a.len().min(b.len()) as f64)
+        let o = Overlap::new(a, b, &self.semantics);
+        Ok(o.intersection / o.len_a.min(o.len_b))
     }
 }
 
@@ -115,12 +170,8 @@ impl Comparator for Jaccard {
     }
 
     fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
-        Ok(zip(a.iter(), b.iter())
-                .filter(|(a, b)| a.is_same(b))
-                .count() as f64
-                / (a.len() + b.len() - zip(a.iter(), b.iter())
-                    .filter(|(a, b)| a.is_same(b))
-                    .count()) as f64)
+        let o = Overlap::new(a, b, &self.semantics);
+        Ok(o.intersection / (o.len_a + o.len_b - o.intersection))
     }
 }
 
@@ -130,21 +181,67 @@ impl Comparator for Dice{
     }
 
     fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
-        Ok(2.0 * zip(a.iter(), b.iter())
-                .filter(|(a, b)| a.is_same(b))
-                .count() as f64
-                / (a.len() + b.len()) as f64)
+        let o = Overlap::new(a, b, &self.semantics);
+        Ok(2.0 * o.intersection / (o.len_a + o.len_b))
+    }
+}
+
+/// The sizes of two birthmarks and the size of their intersection,
+/// counted under the given [`Semantics`].
+struct Overlap {
+    intersection: f64,
+    len_a: f64,
+    len_b: f64,
+}
+
+impl Overlap {
+    fn new(a: &Birthmark, b: &Birthmark, semantics: &Semantics) -> Self {
+        match semantics {
+            Semantics::Set => {
+                let s1 = representatives(a);
+                let s2 = representatives(b);
+                let intersection = s1.iter()
+                    .filter_map(|(k, e1)| s2.get(k).map(|e2| e1.similarity(e2)))
+                    .sum::<f64>();
+                Self {
+                    intersection,
+                    len_a: s1.len() as f64,
+                    len_b: s2.len() as f64,
+                }
+            },
+            Semantics::Multiset => {
+                let m1 = a.weights();
+                let m2 = b.weights();
+                let intersection = m1.iter()
+                    .map(|(k, v)| (*v).min(*m2.get(k).unwrap_or(&0)))
+                    .sum::<usize>();
+                Self {
+                    intersection: intersection as f64,
+                    len_a: m1.values().sum::<usize>() as f64,
+                    len_b: m2.values().sum::<usize>() as f64,
+                }
+            },
+        }
     }
 }
 
+/// returns the first element of each key in the given birthmark.
+fn representatives(b: &Birthmark) -> HashMap<Key<'_>, &Element> {
+    let mut map = HashMap::new();
+    for e in b.iter() {
+        map.entry(e.key()).or_insert(e);
+    }
+    map
+}
+
 impl Comparator for Cosine {
     fn ctype(&self) -> Type {
         Type::Cosine
     }
 
     fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
-        let m1 = a.freq();
-        let m2 = b.freq();
+        let m1 = a.weights();
+        let m2 = b.weights();
         let keys = merge_keys(&m1, &m2);
 
         let dot_product = keys.iter()
@@ -156,7 +253,7 @@ impl Comparator for Cosine {
     }
 }
 
-fn merge_keys<'a>(m1: &'a HashMap<&'a Element, usize>, m2: &'a HashMap<&'a Element, usize>) -> HashSet<&'a Element> {
+fn merge_keys<'a>(m1: &HashMap<Key<'a>, usize>, m2: &HashMap<Key<'a>, usize>) -> HashSet<Key<'a>> {
     let mut keys = HashSet::new();
     for k in m1.keys() {
         keys.insert(*k);
@@ -167,28 +264,119 @@ fn merge_keys<'a>(m1: &'a HashMap<&'a Element, usize>, m2: &'a HashMap<&'a Eleme
     keys
 }
 
+impl Comparator for WeightedJaccard {
+    fn ctype(&self) -> Type {
+        Type::WeightedJaccard
+    }
+
+    fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
+        let w = Weights::new(a, b);
+        Ok(w.min / w.max)
+    }
+}
+
+impl Comparator for WeightedDice {
+    fn ctype(&self) -> Type {
+        Type::WeightedDice
+    }
+
+    fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
+        let w = Weights::new(a, b);
+        Ok(2.0 * w.min / (w.total_a + w.total_b))
+    }
+}
+
+impl Comparator for BrayCurtis {
+    fn ctype(&self) -> Type {
+        Type::BrayCurtis
+    }
+
+    /// Bray-Curtis dissimilarity is the sum of absolute differences over the sum of all counts.
+    /// Since `|a - b| = a + b - 2 min(a, b)` for the counts, the similarity, 1 - the dissimilarity,
+    /// equals the weighted Dice's coefficient, and is computed by it.
+    fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
+        WeightedDice{}.compare_impl(a, b)
+    }
+}
+
+/// The sums over the per-key weights (the counts of [`Element::Freq`], and the number of occurrences otherwise)
+/// of two birthmarks.
+struct Weights {
+    min: f64,
+    max: f64,
+    total_a: f64,
+    total_b: f64,
+}
+
+impl Weights {
+    fn new(a: &Birthmark, b: &Birthmark) -> Self {
+        let m1 = a.weights();
+        let m2 = b.weights();
+        let mut w = Self { min: 0.0, max: 0.0, total_a: 0.0, total_b: 0.0 };
+        for k in merge_keys(&m1, &m2) {
+            let v1 = *m1.get(&k).unwrap_or(&0) as f64;
+            let v2 = *m2.get(&k).unwrap_or(&0) as f64;
+            w.min += v1.min(v2);
+            w.max += v1.max(v2);
+            w.total_a += v1;
+            w.total_b += v2;
+        }
+        w
+    }
+}
+
+impl Comparator for FuzzyHash {
+    fn ctype(&self) -> Type {
+        Type::FuzzyHash
+    }
+
+    fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
+        Ok(Digest::new(a).similarity(&Digest::new(b)))
+    }
+}
+
+impl Comparator for WlKernel {
+    fn ctype(&self) -> Type {
+        Type::WlKernel
+    }
+
+    fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
+        Ok(graphs::wl_kernel(&edge_graph(a)?, &edge_graph(b)?))
+    }
+}
+
+impl Comparator for Graph {
+    fn ctype(&self) -> Type {
+        Type::Graph
+    }
+
+    fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
+        let kernel = graphs::wl_kernel(&edge_graph(a)?, &edge_graph(b)?);
+        let o = Overlap::new(a, b, &Semantics::Set);
+        let jaccard = o.intersection / (o.len_a + o.len_b - o.intersection);
+        Ok((jaccard + kernel) / 2.0)
+    }
+}
+
+/// builds the graph from the edge elements, the k-grams of two node names, such as [`BirthmarkType::CallGraph`].
+fn edge_graph(b: &Birthmark) -> Result<graphs::Graph> {
+    let edges = b.iter()
+        .map(|e| match e {
+            Element::Kgram(items) if items.len() == 2 => Ok((items[0].as_str(), items[1].as_str())),
+            _ => Err(OinkieError::Format(format!("{}: {:?} is not an edge element", b.info.name, e))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(graphs::Graph::from_edges(edges.into_iter()))
+}
+
 impl Comparator for LCS {
     fn ctype(&self) -> Type {
         Type::LCS
     }
 
     fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
-        let len_a = a.len();
-        let len_b = b.len();
-        let lcs_len = {
-            let mut dp = vec![vec![0; len_b + 1]; len_a + 1];
-            for i in 1..=len_a {
-                for j in 1..=len_b {
-                    if a.iter().nth(i - 1).unwrap().is_same(b.iter().nth(j - 1).unwrap()) {
-                        dp[i][j] = dp[i - 1][j - 1] + 1;
-                    } else {
-                        dp[i][j] = dp[i - 1][j].max(dp[i][j - 1]);
-                    }
-                }
-            }
-            dp[len_a][len_b]
-        };
-        Ok(lcs_len as f64 / len_a.max(len_b) as f64)
+        let lcs_len = sequences::lcs(&a.elements, &b.elements);
+        Ok(lcs_len / a.len().max(b.len()) as f64)
     }
 }
 
@@ -198,42 +386,87 @@ impl Comparator for Levenshtein {
     }
 
     fn compare_impl(&self, a: &Birthmark, b: &Birthmark) -> Result<f64> {
-        let len_a = a.len();
-        let len_b = b.len();
-        let dist = edit_distance(a, b);
-        Ok(1.0 - dist as f64 / len_a.max(len_b) as f64)
-    }
-}
-
-fn edit_distance(a: &Birthmark, b: &Birthmark) -> usize {
-    let len_a = a.len();
-    let len_b = b.len();
-    let mut dp = vec![vec![0; len_b + 1]; len_a + 1];
-
-    for i in 0..=len_a {
-        dp[i][0] = i;
-    }
-    for j in 0..=len_b {
-        dp[0][j] = j;
-    }
-
-    for i in 1..=len_a {
-        for j in 1..=len_b {
-            let cost = if a.iter().nth(i - 1).unwrap().is_same(b.iter().nth(j - 1).unwrap()) {
-                0
-            } else {
-                1
-            };
-            dp[i][j] = *[
-                dp[i - 1][j] + 1,     // Deletion
-                dp[i][j - 1] + 1,     // Insertion
-                dp[i - 1][j - 1] + cost, // Substitution
-            ]
-            .iter()
-            .min()
-            .unwrap();
+        let dist = sequences::edit_distance(&a.elements, &b.elements);
+        Ok(1.0 - dist / a.len().max(b.len()) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::extractors::Mode;
+
+    use super::*;
+
+    fn birthmark(elements: &[&str]) -> Birthmark {
+        let info = Info::new("<memory>".into(), PathBuf::from("<memory>"), BirthmarkType::OpSet, Mode::File);
+        Birthmark::new(info, elements.iter().map(|e| Element::Str(e.to_string())).collect())
+    }
+
+    #[test]
+    fn test_set_semantics_ignores_order() {
+        let a = birthmark(&["Add", "Sub", "Ret"]);
+        let b = birthmark(&["Ret", "Add", "Sub"]);
+        for t in [Type::Simpson, Type::Jaccard, Type::Dice] {
+            let s = comparator(&t).compare(&a, &b).unwrap();
+            assert_eq!(s.score, 1.0, "{t}");
         }
     }
 
-    dp[len_a][len_b]
-}
\ No newline at end of file
+    #[test]
+    fn test_set_and_multiset() {
+        let a = birthmark(&["Add", "Add", "Add", "Ret"]);
+        let b = birthmark(&["Add", "Ret", "Call"]);
+
+        let set = comparator_with(&Type::Jaccard, &Semantics::Set);
+        assert_eq!(set.compare(&a, &b).unwrap().score, 2.0 / 3.0);
+        let multiset = comparator_with(&Type::Jaccard, &Semantics::Multiset);
+        assert_eq!(multiset.compare(&a, &b).unwrap().score, 2.0 / 5.0);
+
+        let set = comparator_with(&Type::Dice, &Semantics::Set);
+        assert_eq!(set.compare(&a, &b).unwrap().score, 4.0 / 5.0);
+        let multiset = comparator_with(&Type::Dice, &Semantics::Multiset);
+        assert_eq!(multiset.compare(&a, &b).unwrap().score, 4.0 / 7.0);
+
+        let multiset = comparator_with(&Type::Simpson, &Semantics::Multiset);
+        assert_eq!(multiset.compare(&a, &b).unwrap().score, 2.0 / 3.0);
+    }
+
+    #[test]
+    fn test_freq_partial_credit() {
+        let info = Info::new("<memory>".into(), PathBuf::from("<memory>"), BirthmarkType::OpFreq, Mode::File);
+        let a = Birthmark::new(info.clone(), vec![Element::Freq(3, "Add".into()), Element::Freq(1, "Ret".into())]);
+        let b = Birthmark::new(info, vec![Element::Freq(1, "Ret".into()), Element::Freq(4, "Add".into())]);
+        let s = comparator(&Type::Simpson).compare(&a, &b).unwrap();
+        assert_eq!(s.score, (0.75 + 1.0) / 2.0);
+        let s = comparator(&Type::Cosine).compare(&a, &b).unwrap();
+        assert!((s.score - 13.0 / (10f64.sqrt() * 17f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let info = Info::new("<memory>".into(), PathBuf::from("<memory>"), BirthmarkType::OpFreq, Mode::File);
+        let a = Birthmark::new(info.clone(), vec![Element::Freq(3, "Add".into()), Element::Freq(1, "Ret".into())]);
+        let b = Birthmark::new(info, vec![Element::Freq(4, "Add".into()), Element::Freq(2, "Call".into())]);
+        let s = comparator(&Type::WeightedJaccard).compare(&a, &b).unwrap();
+        assert_eq!(s.score, 3.0 / 7.0);
+        let s = comparator(&Type::WeightedDice).compare(&a, &b).unwrap();
+        assert_eq!(s.score, 6.0 / 10.0);
+        let s = comparator(&Type::BrayCurtis).compare(&a, &b).unwrap();
+        assert_eq!(s.score, 1.0 - 4.0 / 10.0);
+    }
+
+    #[test]
+    fn test_graph_renamed() {
+        let info = Info::new("<memory>".into(), PathBuf::from("<memory>"), BirthmarkType::CallGraph, Mode::File);
+        let edges = |edges: &[(&str, &str)]| edges.iter()
+            .map(|(a, b)| Element::Kgram(vec![a.to_string(), b.to_string()]))
+            .collect::<Vec<_>>();
+        let a = Birthmark::new(info.clone(), edges(&[("main", "fizz"), ("main", "buzz"), ("fizz", "printf")]));
+        let b = Birthmark::new(info, edges(&[("main", "f1"), ("main", "f2"), ("f1", "printf")]));
+        let s = comparator(&Type::WlKernel).compare(&a, &b).unwrap();
+        assert!((s.score - 1.0).abs() < 1e-9);
+        let s = comparator(&Type::Graph).compare(&a, &b).unwrap();
+        assert!((s.score - 0.5).abs() < 1e-9);
+    }
+}
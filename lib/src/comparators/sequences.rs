@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use crate::birthmarks::{Element, Key};
+
+/// The upper bound of the number of words for caching the match vectors of all symbols.
+/// The match vectors are built for each column from the positions of the symbol beyond this size.
+const DENSE_LIMIT: usize = 1 << 22;
+
+/// returns the (weighted) length of the longest common subsequence of the given sequences.
+pub(super) fn lcs(a: &[Element], b: &[Element]) -> f64 {
+    match to_symbols(a, b) {
+        Some((x, y, n)) => lcs_bits(&x, &y, n) as f64,
+        None => lcs_weighted(a, b),
+    }
+}
+
+/// returns the (weighted) edit distance of the given sequences.
+/// The substitution cost of two elements is `1 - similarity`,
+/// so that the partially matched elements cost less than the different ones.
+pub(super) fn edit_distance(a: &[Element], b: &[Element]) -> f64 {
+    match to_symbols(a, b) {
+        Some((x, y, n)) => edit_distance_bits(&x, &y, n) as f64,
+        None => edit_distance_weighted(a, b),
+    }
+}
+
+/// maps the elements into the symbol ids for the bit-parallel algorithms.
+/// Returns `None` if some elements partially match each other (the same key with the different weights),
+/// since the bit-parallel algorithms handle only the exact matches.
+fn to_symbols(a: &[Element], b: &[Element]) -> Option<(Vec<u32>, Vec<u32>, usize)> {
+    let mut ids = HashMap::<&Element, u32>::new();
+    let mut keys = HashMap::<Key<'_>, u32>::new();
+    let mut map = |e| {
+        let next = ids.len() as u32;
+        let id = *ids.entry(e).or_insert(next);
+        match *keys.entry(e.key()).or_insert(id) == id {
+            true => Some(id),
+            false => None,
+        }
+    };
+    let x = a.iter().map(&mut map).collect::<Option<Vec<_>>>()?;
+    let y = b.iter().map(&mut map).collect::<Option<Vec<_>>>()?;
+    Some((x, y, ids.len()))
+}
+
+fn lcs_weighted(a: &[Element], b: &[Element]) -> f64 {
+    let mut prev = vec![0.0; b.len() + 1];
+    let mut curr = vec![0.0; b.len() + 1];
+    for ea in a {
+        for (j, eb) in b.iter().enumerate() {
+            curr[j + 1] = (prev[j] + ea.similarity(eb)).max(prev[j + 1]).max(curr[j]);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+fn edit_distance_weighted(a: &[Element], b: &[Element]) -> f64 {
+    let mut prev = (0..=b.len()).map(|j| j as f64).collect::<Vec<_>>();
+    let mut curr = vec![0.0; b.len() + 1];
+    for (i, ea) in a.iter().enumerate() {
+        curr[0] = (i + 1) as f64;
+        for (j, eb) in b.iter().enumerate() {
+            let cost = 1.0 - ea.similarity(eb);
+            curr[j + 1] = (prev[j] + cost)    // Substitution
+                .min(prev[j + 1] + 1.0)       // Deletion
+                .min(curr[j] + 1.0);          // Insertion
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// The match vectors (the bit vectors of the positions of each symbol) of the pattern.
+struct Pattern {
+    len: usize,
+    words: usize,
+    positions: Vec<Vec<usize>>,
+    dense: Option<Vec<u64>>,
+}
+
+impl Pattern {
+    fn new(pattern: &[u32], symbols: usize) -> Self {
+        let words = pattern.len().div_ceil(64);
+        let mut positions = vec![vec![]; symbols];
+        for (i, &s) in pattern.iter().enumerate() {
+            positions[s as usize].push(i);
+        }
+        let dense = (symbols * words <= DENSE_LIMIT).then(|| {
+            let mut dense = vec![0u64; symbols * words];
+            for (i, &s) in pattern.iter().enumerate() {
+                dense[s as usize * words + i / 64] |= 1 << (i % 64);
+            }
+            dense
+        });
+        Self { len: pattern.len(), words, positions, dense }
+    }
+
+    fn eq<'a>(&'a self, symbol: u32, buf: &'a mut [u64]) -> &'a [u64] {
+        let s = symbol as usize;
+        match &self.dense {
+            Some(dense) => &dense[s * self.words..(s + 1) * self.words],
+            None => {
+                buf.fill(0);
+                for &i in &self.positions[s] {
+                    buf[i / 64] |= 1 << (i % 64);
+                }
+                buf
+            },
+        }
+    }
+}
+
+/// returns the shorter sequence as the pattern, and the other as the text.
+fn pattern_and_text<'a>(a: &'a [u32], b: &'a [u32]) -> (&'a [u32], &'a [u32]) {
+    if a.len() <= b.len() { (a, b) } else { (b, a) }
+}
+
+/// computes the length of LCS by the bit-parallel algorithm of Allison-Dix and Hyyrö,
+/// in `O(ceil(m / 64) * n)` time and `O(m)` space.
+fn lcs_bits(a: &[u32], b: &[u32], symbols: usize) -> usize {
+    let (pattern, text) = pattern_and_text(a, b);
+    if pattern.is_empty() {
+        return 0;
+    }
+    let pattern = Pattern::new(pattern, symbols);
+    let mut buf = vec![0u64; pattern.words];
+    let mut v = vec![!0u64; pattern.words];
+    for &c in text {
+        let eq = pattern.eq(c, &mut buf);
+        let mut carry = false;
+        for (v, &m) in v.iter_mut().zip(eq) {
+            let u = *v & m;
+            let (sum, c1) = v.overflowing_add(u);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            carry = c1 || c2;
+            *v = sum | (*v & !u);
+        }
+    }
+    let ones = v.iter().enumerate()
+        .map(|(w, v)| match (w + 1 == pattern.words, pattern.len % 64) {
+            (true, r) if r != 0 => (v & ((1 << r) - 1)).count_ones(),
+            _ => v.count_ones(),
+        } as usize)
+        .sum::<usize>();
+    pattern.len - ones
+}
+
+/// computes the edit distance by the bit-parallel algorithm of Myers with the blocks of Hyyrö,
+/// in `O(ceil(m / 64) * n)` time and `O(m)` space.
+fn edit_distance_bits(a: &[u32], b: &[u32], symbols: usize) -> usize {
+    let (pattern, text) = pattern_and_text(a, b);
+    if pattern.is_empty() {
+        return text.len();
+    }
+    let pattern = Pattern::new(pattern, symbols);
+    let mut buf = vec![0u64; pattern.words];
+    let mut pv = vec![!0u64; pattern.words];
+    let mut mv = vec![0u64; pattern.words];
+    let last = 1u64 << ((pattern.len - 1) % 64);
+    let mut score = pattern.len as i64;
+    for &c in text {
+        let eq = pattern.eq(c, &mut buf);
+        let mut hin = 1;
+        for w in 0..pattern.words {
+            let high = if w + 1 == pattern.words { last } else { 1 << 63 };
+            hin = advance_block(&mut pv[w], &mut mv[w], eq[w], hin, high);
+        }
+        score += hin as i64;
+    }
+    score as usize
+}
+
+/// advances a block of the vertical deltas (`pv` and `mv`) by a column,
+/// and returns the horizontal delta at the bit `high`.
+fn advance_block(pv: &mut u64, mv: &mut u64, eq: u64, hin: i32, high: u64) -> i32 {
+    let hin_neg = (hin < 0) as u64;
+    let xv = eq | *mv;
+    let eq = eq | hin_neg;
+    let xh = ((eq & *pv).wrapping_add(*pv) ^ *pv) | eq;
+    let mut ph = *mv | !(xh | *pv);
+    let mut mh = *pv & xh;
+    let hout = if ph & high != 0 {
+        1
+    } else if mh & high != 0 {
+        -1
+    } else {
+        0
+    };
+    ph = (ph << 1) | (hin > 0) as u64;
+    mh = (mh << 1) | hin_neg;
+    *pv = mh | !(xv | ph);
+    *mv = ph & xv;
+    hout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lcs_naive(a: &[u32], b: &[u32]) -> usize {
+        let mut dp = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                dp[i + 1][j + 1] = if x == y { dp[i][j] + 1 } else { dp[i][j + 1].max(dp[i + 1][j]) };
+            }
+        }
+        dp[a.len()][b.len()]
+    }
+
+    fn edit_distance_naive(a: &[u32], b: &[u32]) -> usize {
+        let mut dp = vec![vec![0; b.len() + 1]; a.len() + 1];
+        (0..=a.len()).for_each(|i| dp[i][0] = i);
+        (0..=b.len()).for_each(|j| dp[0][j] = j);
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                let cost = if x == y { 0 } else { 1 };
+                dp[i + 1][j + 1] = (dp[i][j] + cost).min(dp[i][j + 1] + 1).min(dp[i + 1][j] + 1);
+            }
+        }
+        dp[a.len()][b.len()]
+    }
+
+    fn sequence(seed: &mut u64, len: usize, symbols: u32) -> Vec<u32> {
+        (0..len).map(|_| {
+            *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((*seed >> 33) % symbols as u64) as u32
+        }).collect()
+    }
+
+    #[test]
+    fn test_bit_parallel_matches_dp() {
+        let mut seed = 42;
+        for (la, lb, symbols) in [(0, 5, 3), (1, 1, 2), (10, 7, 3), (63, 64, 4), (64, 65, 2), (130, 97, 5), (200, 300, 8)] {
+            let a = sequence(&mut seed, la, symbols);
+            let b = sequence(&mut seed, lb, symbols);
+            assert_eq!(lcs_bits(&a, &b, symbols as usize), lcs_naive(&a, &b), "lcs {la} {lb}");
+            assert_eq!(edit_distance_bits(&a, &b, symbols as usize), edit_distance_naive(&a, &b), "ed {la} {lb}");
+        }
+    }
+
+    #[test]
+    fn test_partial_match() {
+        let a = vec![Element::Freq(2, "Add".into()), Element::Freq(1, "Ret".into())];
+        let b = vec![Element::Freq(4, "Add".into()), Element::Freq(1, "Ret".into())];
+        assert_eq!(lcs(&a, &b), 1.5);
+        assert_eq!(edit_distance(&a, &b), 0.5);
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::birthmarks::{Birthmark, BirthmarkType};
+use crate::comparators::{comparator_with, Comparator, Semantics, Similarity, Type};
+use crate::extractors::{self, Mode, Options};
+use crate::{OinkieError, Result};
+
+/// The birthmarks of several types extracted from the same unit, such as a file, a function, or a basic block.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Composite {
+    pub name: String,
+    pub path: PathBuf,
+    pub mode: Mode,
+    pub birthmarks: Vec<Birthmark>,
+}
+
+impl Composite {
+    pub fn get(&self, btype: &BirthmarkType) -> Option<&Birthmark> {
+        self.birthmarks.iter().find(|b| &b.info.btype == btype)
+    }
+}
+
+pub fn from_path<P: AsRef<Path>>(path: P, btypes: &[BirthmarkType], mode: &Mode, opts: &Options) -> Result<Vec<Composite>> {
+    let result = extractors::parse(&path.as_ref().to_path_buf())?
+        .into_iter()
+        .map(|(path, module)| extract(&module, path, btypes, mode, opts))
+        .collect::<Vec<_>>();
+    OinkieError::vec_result_to_result_vec(result)
+        .map(|v| v.into_iter().flatten().collect())
+}
+
+/// extracts the composite birthmarks from the IR/BC in memory, named `<bytes>`.
+pub fn from_bytes(bytes: &[u8], btypes: &[BirthmarkType], mode: &Mode, opts: &Options) -> Result<Vec<Composite>> {
+    let result = extractors::parse_bytes(Path::new(extractors::IN_MEMORY), bytes)?
+        .into_iter()
+        .map(|(path, module)| extract(&module, path, btypes, mode, opts))
+        .collect::<Vec<_>>();
+    OinkieError::vec_result_to_result_vec(result)
+        .map(|v| v.into_iter().flatten().collect())
+}
+
+/// extracts the composite birthmarks of the given types in a single traversal of the module.
+/// The birthmarks of the same unit are bundled into a composite birthmark.
+pub fn extract<P: AsRef<Path>>(module: &llvm_ir::Module, path: P, btypes: &[BirthmarkType], mode: &Mode, opts: &Options) -> Result<Vec<Composite>> {
+    let types = btypes.iter()
+        .map(|bt| (bt.clone(), mode.clone()))
+        .collect::<Vec<_>>();
+    let mut birthmarks = extractors::extract_each(module, path, &types, opts)?
+        .into_iter()
+        .map(|b| b.into_iter())
+        .collect::<Vec<_>>();
+    let mut composites = vec![];
+    while let Some(parts) = birthmarks.iter_mut().map(|b| b.next()).collect::<Option<Vec<_>>>() {
+        let info = &parts[0].info;
+        composites.push(Composite { name: info.name.clone(), path: info.path.clone(), mode: info.mode.clone(), birthmarks: parts });
+    }
+    Ok(composites)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Composite>> {
+    match std::fs::File::open(path.as_ref()) {
+        Ok(file) => serde_json::from_reader(file).map_err(OinkieError::Json),
+        Err(e) => Err(OinkieError::Io(e)),
+    }
+}
+
+/// The weight of the similarity of a birthmark type in the composite similarity,
+/// given as `BIRTHMARK_TYPE:COMPARATOR[:WEIGHT]`, such as `op-seq:lcs:2`.
+/// The weight is 1 if omitted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weight {
+    pub btype: BirthmarkType,
+    pub ctype: Type,
+    pub weight: f64,
+}
+
+impl FromStr for Weight {
+    type Err = OinkieError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let items = s.split(':').collect::<Vec<_>>();
+        let (btype, ctype, weight) = match items.as_slice() {
+            [btype, ctype] => (btype, ctype, "1"),
+            [btype, ctype, weight] => (btype, ctype, *weight),
+            _ => return Err(OinkieError::Format(format!("{}: expected BIRTHMARK_TYPE:COMPARATOR[:WEIGHT]", s))),
+        };
+        let btype = BirthmarkType::from_str(btype, true)
+            .map_err(|e| OinkieError::Format(format!("{}: {}", s, e)))?;
+        let ctype = <Type as ValueEnum>::from_str(ctype, true)
+            .map_err(|e| OinkieError::Format(format!("{}: {}", s, e)))?;
+        match weight.parse::<f64>() {
+            Ok(weight) if weight >= 0.0 => Ok(Self { btype, ctype, weight }),
+            _ => Err(OinkieError::Format(format!("{}: the weight must be a non-negative number", s))),
+        }
+    }
+}
+
+/// The similarity of two composite birthmarks with the similarities of their parts.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompositeSimilarity {
+    pub a_name: String,
+    pub b_name: String,
+    pub score: f64,
+    pub similarities: Vec<Similarity>,
+}
+
+/// compares the composite birthmarks by the weighted mean of the similarities of the weighted types.
+pub struct CompositeComparator {
+    weights: Vec<(Weight, Box<dyn Comparator>)>,
+}
+
+impl CompositeComparator {
+    pub fn new(weights: Vec<Weight>, semantics: &Semantics) -> Self {
+        let weights = weights.into_iter()
+            .map(|w| { let c = comparator_with(&w.ctype, semantics); (w, c) })
+            .collect();
+        Self { weights }
+    }
+
+    pub fn compare(&self, a: &Composite, b: &Composite) -> Result<CompositeSimilarity> {
+        let mut similarities = vec![];
+        let (mut sum, mut total) = (0.0, 0.0);
+        for (weight, comparator) in &self.weights {
+            let (ba, bb) = match (a.get(&weight.btype), b.get(&weight.btype)) {
+                (Some(ba), Some(bb)) => (ba, bb),
+                (None, _) => return Err(OinkieError::Fatal(format!("{}: no birthmark of {:?}", a.name, weight.btype))),
+                (_, None) => return Err(OinkieError::Fatal(format!("{}: no birthmark of {:?}", b.name, weight.btype))),
+            };
+            if !ba.is_same_type(bb) {
+                return Err(OinkieError::Fatal(format!("Birthmark types do not match: {:?} vs {:?}", ba.info, bb.info)));
+            }
+            let similarity = comparator.compare(ba, bb)?;
+            sum += weight.weight * similarity.score;
+            total += weight.weight;
+            similarities.push(similarity);
+        }
+        let score = if total > 0.0 { sum / total } else { 0.0 };
+        Ok(CompositeSimilarity { a_name: a.name.clone(), b_name: b.name.clone(), score, similarities })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::birthmarks::{Element, Info};
+
+    use super::*;
+
+    fn composite(name: &str, ops: &[&str], calls: &[&str]) -> Composite {
+        let birthmark = |bt: BirthmarkType, elements: &[&str]| {
+            let info = Info::new(name.into(), PathBuf::from(name), bt, Mode::File);
+            Birthmark::new(info, elements.iter().map(|e| Element::Str(e.to_string())).collect())
+        };
+        Composite {
+            name: name.into(), path: PathBuf::from(name), mode: Mode::File,
+            birthmarks: vec![birthmark(BirthmarkType::OpSeq, ops), birthmark(BirthmarkType::Sfc, calls)],
+        }
+    }
+
+    #[test]
+    fn test_weight() {
+        let weight = "op-seq:lcs:2".parse::<Weight>().unwrap();
+        assert_eq!(weight, Weight { btype: BirthmarkType::OpSeq, ctype: Type::LCS, weight: 2.0 });
+        assert_eq!("sfc:jaccard".parse::<Weight>().unwrap().weight, 1.0);
+        assert!("sfc".parse::<Weight>().is_err());
+        assert!("sfc:jaccard:-1".parse::<Weight>().is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = composite("a", &["Add", "Sub", "Ret"], &["puts", "printf"]);
+        let b = composite("b", &["Add", "Sub", "Ret"], &["puts", "exit"]);
+        let comparator = CompositeComparator::new(vec![
+            "op-seq:jaccard:3".parse().unwrap(),
+            "sfc:jaccard:1".parse().unwrap(),
+        ], &Semantics::default());
+        let similarity = comparator.compare(&a, &b).unwrap();
+        assert_eq!(similarity.similarities.len(), 2);
+        assert!((similarity.score - (3.0 * 1.0 + 1.0 / 3.0) / 4.0).abs() < 1e-9);
+    }
+}
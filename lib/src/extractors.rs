@@ -1,19 +1,36 @@
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use clap::ValueEnum;
 
 use crate::birthmarks::{Birthmark, BirthmarkType, Element, Info};
+use crate::objects;
 use crate::{OinkieError, Result};
 
+mod cfg;
 mod functions;
+mod globals;
 mod opcodes;
 mod operands;
 
+pub use functions::NameForm;
+pub use opcodes::{Abstraction, Boundary};
+
 pub enum Source {
     BC,
     IR,
+    Elf,
+    Archive,
+    Gzip,
+    Zstd,
 }
 
+/// The length of the head of the files for detecting the formats.
+const SNIFF_LEN: usize = 4096;
+
+/// The name of the IR/BC in memory.
+pub(crate) const IN_MEMORY: &str = "<bytes>";
+
 /// The extraction mode of the birthmark.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, ValueEnum, serde::Serialize, serde::Deserialize)]
 pub enum Mode {
@@ -25,46 +42,222 @@ pub enum Mode {
     BasicBlock,
 }
 
+/// The options of the extractors, which are shared among the birthmark types.
+#[derive(clap::Args, Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    #[clap(long = "names", value_name = "NAME_FORM", default_value = "raw", help = "Form of the function names in the call birthmarks")]
+    pub names: NameForm,
+
+    #[clap(long = "abstraction", value_name = "ABSTRACTION", default_value = "opcode", help = "Abstraction level of the instruction labels in the opcode birthmarks")]
+    pub abstraction: Abstraction,
+
+    #[clap(long = "min-string-length", value_name = "LENGTH", default_value_t = 4, help = "Ignores the shorter strings in the global data birthmarks")]
+    pub min_string_length: usize,
+
+    #[clap(long = "max-table-length", value_name = "LENGTH", default_value_t = 64, help = "Hashes the longer tables in the global data birthmarks")]
+    pub max_table_length: usize,
+
+    #[clap(long = "kgram-size", value_name = "K", default_value_t = 3, help = "Length of the k-grams of k-gram and call-k-gram")]
+    pub kgram_size: usize,
+
+    #[clap(long = "kgram-boundary", value_name = "BOUNDARY", default_value = "function", help = "Boundaries which the k-grams do not cross")]
+    pub kgram_boundary: Boundary,
+
+    #[clap(long = "skip-size", value_name = "S", default_value_t = 2, help = "Maximum number of the skipped elements of skip-gram")]
+    pub skip_size: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            names: NameForm::default(),
+            abstraction: Abstraction::default(),
+            min_string_length: 4,
+            max_table_length: 64,
+            kgram_size: 3,
+            kgram_boundary: Boundary::default(),
+            skip_size: 2,
+        }
+    }
+}
+
 pub fn from_str(string: &str, bt: &BirthmarkType, mode: &Mode) -> Result<Vec<Birthmark>> {
+    from_str_with(string, bt, mode, &Options::default())
+}
+
+pub fn from_str_with(string: &str, bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Result<Vec<Birthmark>> {
     match llvm_ir::Module::from_ir_str(string) {
-        Ok(module) => extract(&module, PathBuf::from("<str>"), bt, mode),
+        Ok(module) => extract_with(&module, PathBuf::from("<str>"), bt, mode, opts),
         Err(e) => Err(OinkieError::Format(format!("Failed to parse IR from reader: {}", e))),
     }
 }
 
 pub fn from_path<P: AsRef<Path>>(path: P, bt: &BirthmarkType, mode: &Mode) -> Result<Vec<Birthmark>> {
-    let path = path.as_ref().to_path_buf();
-    match find_type(&path) {
-        Ok(t) => match parse_impl(&path, t) {
-            Ok(module) => extract(&module, path, bt, mode),
-            Err(e) => Err(e),
-        },
-        Err(e) => Err(e),
+    from_path_with(path, bt, mode, &Options::default())
+}
+
+pub fn from_path_with<P: AsRef<Path>>(path: P, bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Result<Vec<Birthmark>> {
+    extract_modules(parse(&path.as_ref().to_path_buf())?, bt, mode, opts)
+}
+
+/// extracts the birthmarks from the IR/BC in memory, such as the bitcode piped from `clang -emit-llvm -c -o -`.
+/// The format is detected by the content as the files, and the birthmarks are named `<bytes>`.
+pub fn from_bytes(bytes: &[u8], bt: &BirthmarkType, mode: &Mode) -> Result<Vec<Birthmark>> {
+    from_bytes_with(bytes, bt, mode, &Options::default())
+}
+
+pub fn from_bytes_with(bytes: &[u8], bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Result<Vec<Birthmark>> {
+    extract_modules(parse_bytes(Path::new(IN_MEMORY), bytes)?, bt, mode, opts)
+}
+
+/// extracts the birthmarks from the IR/BC read to the end of the reader, such as the standard input.
+pub fn from_reader<R: Read>(reader: R, bt: &BirthmarkType, mode: &Mode) -> Result<Vec<Birthmark>> {
+    from_reader_with(reader, bt, mode, &Options::default())
+}
+
+pub fn from_reader_with<R: Read>(mut reader: R, bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Result<Vec<Birthmark>> {
+    let mut bytes = vec![];
+    match reader.read_to_end(&mut bytes) {
+        Ok(_) => from_bytes_with(&bytes, bt, mode, opts),
+        Err(e) => Err(OinkieError::Io(e)),
     }
 }
 
+/// reads the bitcode modules and the compiler arguments embedded in the ELF file, without parsing the modules.
+pub fn embedded_from_path<P: AsRef<Path>>(path: P) -> Result<Vec<objects::Embedded>> {
+    match std::fs::read(path.as_ref()) {
+        Ok(data) => objects::read_elf(&data),
+        Err(e) => Err(OinkieError::Io(e)),
+    }
+}
+
+fn extract_modules(modules: Vec<(PathBuf, llvm_ir::Module)>, bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Result<Vec<Birthmark>> {
+    let result = modules.into_iter()
+        .map(|(path, module)| extract_with(&module, path, bt, mode, opts))
+        .collect::<Vec<_>>();
+    OinkieError::vec_result_to_result_vec(result)
+        .map(|v| v.into_iter().flatten().collect())
+}
+
 pub fn extract<P: AsRef<Path>>(module: &llvm_ir::Module, path: P, bt: &BirthmarkType, mode: &Mode) -> Result<Vec<Birthmark>> {
+    extract_with(module, path, bt, mode, &Options::default())
+}
+
+pub fn extract_with<P: AsRef<Path>>(module: &llvm_ir::Module, path: P, bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Result<Vec<Birthmark>> {
+    extract_each(module, path, &[(bt.clone(), mode.clone())], opts)
+        .map(|mut birthmarks| birthmarks.remove(0))
+}
+
+/// The birthmarks of a birthmark type extracted in an extraction mode.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Group {
+    pub btype: BirthmarkType,
+    pub mode: Mode,
+    pub birthmarks: Vec<Birthmark>,
+}
+
+impl Group {
+    /// tells whether the birthmarks of the groups are comparable, by the types of their first birthmarks,
+    /// including the parameters, such as `k` of the k-grams.
+    /// The empty groups are judged only by the birthmark types and the modes.
+    pub fn is_same_type(&self, other: &Group) -> bool {
+        self.btype == other.btype && self.mode == other.mode
+            && match (self.birthmarks.first(), other.birthmarks.first()) {
+                (Some(a), Some(b)) => a.is_same_type(b),
+                _ => true,
+            }
+    }
+}
+
+/// merges the groups of the same birthmark type, in the order of their first appearances.
+/// The groups of the same birthmark type and mode with the different parameters are kept apart.
+pub fn merge_groups<I: IntoIterator<Item = Group>>(groups: I) -> Vec<Group> {
+    let mut merged: Vec<Group> = vec![];
+    for group in groups {
+        match merged.iter_mut().find(|g| g.is_same_type(&group)) {
+            Some(g) => g.birthmarks.extend(group.birthmarks),
+            None => merged.push(group),
+        }
+    }
+    merged
+}
+
+/// parses the IR/BC file once, and extracts the birthmarks of all given combinations of the types and the modes.
+pub fn from_path_many<P: AsRef<Path>>(path: P, types: &[(BirthmarkType, Mode)], opts: &Options) -> Result<Vec<Group>> {
+    extract_modules_many(parse(&path.as_ref().to_path_buf())?, types, opts)
+}
+
+/// parses the IR/BC in memory once, and extracts the birthmarks of all given combinations of the types and the modes.
+pub fn from_bytes_many(bytes: &[u8], types: &[(BirthmarkType, Mode)], opts: &Options) -> Result<Vec<Group>> {
+    extract_modules_many(parse_bytes(Path::new(IN_MEMORY), bytes)?, types, opts)
+}
+
+fn extract_modules_many(modules: Vec<(PathBuf, llvm_ir::Module)>, types: &[(BirthmarkType, Mode)], opts: &Options) -> Result<Vec<Group>> {
+    let result = modules.into_iter()
+        .map(|(path, module)| extract_many(&module, path, types, opts))
+        .collect::<Vec<_>>();
+    let mut groups = types.iter()
+        .map(|(btype, mode)| Group { btype: btype.clone(), mode: mode.clone(), birthmarks: vec![] })
+        .collect::<Vec<_>>();
+    for module in OinkieError::vec_result_to_result_vec(result)? {
+        groups.iter_mut().zip(module)
+            .for_each(|(group, g)| group.birthmarks.extend(g.birthmarks));
+    }
+    Ok(groups)
+}
+
+/// extracts the birthmarks of all given combinations of the types and the modes in a single traversal of the module,
+/// and returns them grouped in the order of the given combinations.
+pub fn extract_many<P: AsRef<Path>>(module: &llvm_ir::Module, path: P, types: &[(BirthmarkType, Mode)], opts: &Options) -> Result<Vec<Group>> {
+    extract_each(module, path, types, opts).map(|birthmarks| types.iter()
+        .zip(birthmarks)
+        .map(|((btype, mode), birthmarks)| Group { btype: btype.clone(), mode: mode.clone(), birthmarks })
+        .collect())
+}
+
+/// extracts the birthmarks of the given types and modes in a single traversal of the module,
+/// and returns them in the order of the given types and modes.
+pub(crate) fn extract_each<P: AsRef<Path>>(module: &llvm_ir::Module, path: P, types: &[(BirthmarkType, Mode)], opts: &Options) -> Result<Vec<Vec<Birthmark>>> {
+    for (bt, _) in types {
+        if matches!(bt, BirthmarkType::KGram | BirthmarkType::CallKGram | BirthmarkType::SkipGram) && opts.kgram_size == 0 {
+            return Err(OinkieError::Fatal("The k-gram size must be positive".to_string()));
+        }
+    }
     let path = path.as_ref().to_path_buf();
-    let mut extractor = build_extractor(bt, mode);
-    extract_birthmarks_impl(&module, &mut extractor, &path)
+    let mut extractors = types.iter()
+        .map(|(bt, mode)| build_extractor(bt, mode, opts))
+        .collect::<Vec<_>>();
+    extract_birthmarks_impl(module, &mut extractors, &path)
 }
 
-fn build_extractor(bt: &BirthmarkType, mode: &Mode) -> Box<dyn Extractor> {
+fn build_extractor(bt: &BirthmarkType, mode: &Mode, opts: &Options) -> Box<dyn Extractor> {
     use BirthmarkType::*;
+    let kgrams = |extractor: opcodes::KGramExtractor| -> Box<dyn Extractor> {
+        Box::new(extractor.with_boundary(opts.kgram_boundary).with_mode(mode))
+    };
     let extractor: Box<dyn Extractor> = match bt {
-        OpSeq => Box::new(opcodes::SeqExtractor::new()),
-        OpSet => Box::new(opcodes::SetExtractor::new()),
-        OpFreq => Box::new(opcodes::FreqExtractor::new()),
-        UniGram => Box::new(opcodes::KGramExtractor::new(1)),
-        BiGram => Box::new(opcodes::KGramExtractor::new(2)),
-        TriGram => Box::new(opcodes::KGramExtractor::new(3)),
-        TetraGram => Box::new(opcodes::KGramExtractor::new(4)),
-        PentaGram => Box::new(opcodes::KGramExtractor::new(5)),
-        HexaGram => Box::new(opcodes::KGramExtractor::new(6)),
-        HeptaGram => Box::new(opcodes::KGramExtractor::new(7)),
-        OctaGram => Box::new(opcodes::KGramExtractor::new(8)),
-        Sfc => Box::new(functions::SeqNames::new()),
-        Ffc => Box::new(functions::FreqNames::new()),
+        OpSeq => Box::new(opcodes::SeqExtractor::new(opts.abstraction)),
+        OpSet => Box::new(opcodes::SetExtractor::new(opts.abstraction)),
+        OpFreq => Box::new(opcodes::FreqExtractor::new(opts.abstraction)),
+        UniGram => kgrams(opcodes::KGramExtractor::new(1, opts.abstraction)),
+        BiGram => kgrams(opcodes::KGramExtractor::new(2, opts.abstraction)),
+        TriGram => kgrams(opcodes::KGramExtractor::new(3, opts.abstraction)),
+        TetraGram => kgrams(opcodes::KGramExtractor::new(4, opts.abstraction)),
+        PentaGram => kgrams(opcodes::KGramExtractor::new(5, opts.abstraction)),
+        HexaGram => kgrams(opcodes::KGramExtractor::new(6, opts.abstraction)),
+        HeptaGram => kgrams(opcodes::KGramExtractor::new(7, opts.abstraction)),
+        OctaGram => kgrams(opcodes::KGramExtractor::new(8, opts.abstraction)),
+        KGram => kgrams(opcodes::KGramExtractor::arbitrary(opts.kgram_size, opts.abstraction)),
+        CallKGram => kgrams(opcodes::KGramExtractor::calls(opts.kgram_size, opts.names)),
+        SkipGram => Box::new(opcodes::SkipGramExtractor::new(opts.kgram_size, opts.skip_size, opts.abstraction, opts.kgram_boundary)),
+        Sfc => Box::new(functions::SeqNames::new(opts.names)),
+        Ffc => Box::new(functions::FreqNames::new(opts.names)),
+        CallGraph => Box::new(functions::CallGraph::new(opts.names)),
+        CfgInv => Box::new(cfg::CfgExtractor::new(cfg::CfgKind::Invariants)),
+        CfgHash => Box::new(cfg::CfgExtractor::new(cfg::CfgKind::Hash)),
+        CfgWl => Box::new(cfg::CfgExtractor::new(cfg::CfgKind::Wl)),
+        Cvfv => Box::new(operands::ConstantExtractor::new()),
+        GlobalData => Box::new(globals::GlobalDataExtractor::new(opts.min_string_length, opts.max_table_length)),
     };
     match mode {
         Mode::Function => Box::new(FunctionModeExtractor::new(extractor)),
@@ -73,10 +266,127 @@ fn build_extractor(bt: &BirthmarkType, mode: &Mode) -> Box<dyn Extractor> {
     }
 }
 
-fn parse_impl(path: &PathBuf, source: Source) -> Result<llvm_ir::Module> {
+/// parses the IR/BC file into the modules with their names, by the file type.
+/// The IR/BC files have a module named by the path,
+/// and the ELF files have the modules of the embedded bitcode,
+/// named by the path and the source file name of the module, such as `a.out(src/main.c)`, if there are several.
+/// The `ar` archives have the modules of the bitcode members and the ELF members with the embedded bitcode,
+/// named by the archive and the member, such as `libfoo.a(foo.o)`.
+/// The gzip/zstd compressed files are decompressed into the above formats, but not into the compressed ones.
+pub(crate) fn parse(path: &PathBuf) -> Result<Vec<(PathBuf, llvm_ir::Module)>> {
+    match find_type(path) {
+        Ok(t) => parse_impl(path, t),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_impl(path: &PathBuf, source: Source) -> Result<Vec<(PathBuf, llvm_ir::Module)>> {
     match source {
-        Source::BC => parse_bc(path),
-        Source::IR => parse_ir(path),
+        Source::BC => parse_bc(path).map(|m| vec![(path.clone(), m)]),
+        Source::IR => parse_ir(path).map(|m| vec![(path.clone(), m)]),
+        _ => match std::fs::read(path) {
+            Ok(data) => parse_data(path, &data, source),
+            Err(e) => Err(OinkieError::Io(e)),
+        },
+    }
+}
+
+/// parses the IR/BC in memory into the modules with their names, by the content.
+pub(crate) fn parse_bytes(name: &Path, bytes: &[u8]) -> Result<Vec<(PathBuf, llvm_ir::Module)>> {
+    match sniff(bytes) {
+        Some(source) => parse_data(name, bytes, source),
+        None => Err(OinkieError::UnsupportedFormat(format!("{}: unknown format", name.display()))),
+    }
+}
+
+fn parse_data(path: &Path, data: &[u8], source: Source) -> Result<Vec<(PathBuf, llvm_ir::Module)>> {
+    match source {
+        Source::BC => parse_bc_bytes(data).map(|m| vec![(path.to_path_buf(), m)]),
+        Source::IR => parse_ir_bytes(data).map(|m| vec![(path.to_path_buf(), m)]),
+        Source::Elf => match parse_embedded(path, data) {
+            Ok(modules) if modules.is_empty() =>
+                Err(OinkieError::Format(format!("{}: no embedded bitcode (.llvmbc section)", path.display()))),
+            result => result,
+        },
+        Source::Archive => parse_archive(path, data),
+        Source::Gzip | Source::Zstd => {
+            let data = objects::decompress(data)?;
+            // the extension of the decompressed file, such as `ll` of `main.ll.gz`.
+            let inner = Path::new(path.file_stem().unwrap_or_default());
+            match sniff(&data) {
+                Some(Source::Gzip | Source::Zstd) =>
+                    Err(OinkieError::Format(format!("{}: compressed data in the compressed data", path.display()))),
+                Some(source) => parse_data(path, &data, source),
+                None => parse_data(path, &data, by_extension(inner)?),
+            }
+        },
+    }
+}
+
+fn parse_archive(path: &Path, data: &[u8]) -> Result<Vec<(PathBuf, llvm_ir::Module)>> {
+    let mut result = vec![];
+    for member in objects::read_archive(data)? {
+        let name = PathBuf::from(format!("{}({})", path.display(), member.name));
+        if objects::is_bitcode(member.data) {
+            result.push(parse_bc_bytes(member.data).map(|m| vec![(name, m)]));
+        } else if objects::is_elf(member.data) {
+            result.push(parse_embedded(&name, member.data));
+        }
+    }
+    match OinkieError::vec_result_to_result_vec(result) {
+        Ok(modules) if modules.iter().all(|m| m.is_empty()) =>
+            Err(OinkieError::Format(format!("{}: no bitcode members", path.display()))),
+        result => result.map(|modules| modules.into_iter().flatten().collect()),
+    }
+}
+
+/// parses the bitcode embedded in the ELF data, which may have no bitcode.
+fn parse_embedded(path: &Path, data: &[u8]) -> Result<Vec<(PathBuf, llvm_ir::Module)>> {
+    let modules = OinkieError::vec_result_to_result_vec(objects::read_elf(data)?.iter()
+        .map(|embedded| parse_bc_bytes(&embedded.bitcode))
+        .collect())?;
+    if modules.len() <= 1 {
+        return Ok(modules.into_iter().map(|m| (path.to_path_buf(), m)).collect());
+    }
+    Ok(modules.into_iter().enumerate()
+        .map(|(i, m)| {
+            let name = if m.source_file_name.is_empty() { i.to_string() } else { m.source_file_name.clone() };
+            (PathBuf::from(format!("{}({})", path.display(), name)), m)
+        })
+        .collect())
+}
+
+/// parses the bitcode in memory.
+/// `llvm_ir` reads the bitcode only from the files, so that LLVM parses the bitcode from a memory buffer,
+/// and prints the module as the textual IR for `llvm_ir`.
+fn parse_bc_bytes(bytes: &[u8]) -> Result<llvm_ir::Module> {
+    let ir = bitcode_to_ir(objects::unwrap_bitcode(bytes)?)?;
+    llvm_ir::Module::from_ir_str(&ir)
+        .map_err(|e| OinkieError::Format(format!("Failed to parse BC: {}", e)))
+}
+
+fn bitcode_to_ir(bytes: &[u8]) -> Result<String> {
+    use llvm_sys::bit_reader::LLVMParseBitcodeInContext2;
+    use llvm_sys::core::*;
+
+    // SAFETY: the buffer refers to the bytes outliving it, and all LLVM objects are disposed in this function.
+    unsafe {
+        let context = LLVMContextCreate();
+        let buffer = LLVMCreateMemoryBufferWithMemoryRange(bytes.as_ptr() as *const _, bytes.len(), c"<bytes>".as_ptr(), 0);
+        let mut module = std::ptr::null_mut();
+        let failed = LLVMParseBitcodeInContext2(context, buffer, &mut module);
+        LLVMDisposeMemoryBuffer(buffer);
+        let result = if failed != 0 {
+            Err(OinkieError::Format("Failed to parse BC: broken bitcode".to_string()))
+        } else {
+            let message = LLVMPrintModuleToString(module);
+            let ir = std::ffi::CStr::from_ptr(message).to_string_lossy().into_owned();
+            LLVMDisposeMessage(message);
+            LLVMDisposeModule(module);
+            Ok(ir)
+        };
+        LLVMContextDispose(context);
+        result
     }
 }
 
@@ -90,23 +400,113 @@ fn parse_ir(path: &PathBuf) -> Result<llvm_ir::Module> {
         .map_err(|e| OinkieError::Format(format!("Failed to parse IR file: {}", e)))
 }
 
-fn find_type(path: &PathBuf) -> Result<Source> {
+fn parse_ir_bytes(bytes: &[u8]) -> Result<llvm_ir::Module> {
+    let string = std::str::from_utf8(bytes)
+        .map_err(|e| OinkieError::Format(format!("Failed to parse IR: {}", e)))?;
+    llvm_ir::Module::from_ir_str(string)
+        .map_err(|e| OinkieError::Format(format!("Failed to parse IR: {}", e)))
+}
+
+/// detects the file type by the content, or by the extension if the content is unknown.
+fn find_type(path: &Path) -> Result<Source> {
     if !path.exists() {
-        Err(OinkieError::NotFound(path.clone()))
+        Err(OinkieError::NotFound(path.to_path_buf()))
     } else if path.is_dir() {
-        Err(OinkieError::NotFile(path.clone()))
+        Err(OinkieError::NotFile(path.to_path_buf()))
     } else {
-        match path.extension().and_then(|s| s.to_str()) {
-            Some("bc") => Ok(Source::BC),
-            Some("ll") => Ok(Source::IR),
-            Some(ext) => Err(OinkieError::UnsupportedFormat(ext.to_string())),
-            None => Err(OinkieError::NoExtension(path.to_string_lossy().to_string())),
+        match sniff(&read_head(path, SNIFF_LEN)) {
+            Some(source) => Ok(source),
+            None => by_extension(path),
         }
     }
 }
 
+/// tells whether the file has the IR/BC to extract, for picking up the files in the directories.
+/// The file is judged by the magic bytes of its head, and the compressed files by the inner extensions, such as `main.ll.gz`.
+/// The deep check reads the ELF files, the archives, and the other compressed files entirely to find the bitcode in them.
+pub(crate) fn has_bitcode(path: &Path, deep: bool) -> bool {
+    match sniff(&read_head(path, SNIFF_LEN)) {
+        Some(Source::BC | Source::IR) => true,
+        Some(Source::Gzip | Source::Zstd) if by_extension(Path::new(path.file_stem().unwrap_or_default())).is_ok() => true,
+        Some(_) if deep => std::fs::read(path).is_ok_and(|data| contains_bitcode(&data, true)),
+        _ => false,
+    }
+}
+
+/// tells whether the data has the IR/BC, looking into the embedded bitcode, the archive members,
+/// and the decompressed data only if the data is at the top level.
+fn contains_bitcode(data: &[u8], top: bool) -> bool {
+    let has_embedded = |data: &[u8]| objects::read_elf(data).is_ok_and(|modules| !modules.is_empty());
+    match sniff(data) {
+        Some(Source::BC | Source::IR) => true,
+        Some(Source::Elf) => has_embedded(data),
+        Some(Source::Archive) => objects::read_archive(data)
+            .is_ok_and(|members| members.iter().any(|m| objects::is_bitcode(m.data) || has_embedded(m.data))),
+        Some(Source::Gzip | Source::Zstd) if top => objects::decompress(data)
+            .is_ok_and(|data| contains_bitcode(&data, false)),
+        _ => false,
+    }
+}
+
+/// detects the format by the magic bytes, or the textual IR by the heuristics.
+fn sniff(head: &[u8]) -> Option<Source> {
+    if objects::is_bitcode(head) {
+        Some(Source::BC)
+    } else if objects::is_elf(head) {
+        Some(Source::Elf)
+    } else if objects::is_archive(head) {
+        Some(Source::Archive)
+    } else if objects::is_gzip(head) {
+        Some(Source::Gzip)
+    } else if objects::is_zstd(head) {
+        Some(Source::Zstd)
+    } else if objects::is_ir(head) {
+        Some(Source::IR)
+    } else {
+        None
+    }
+}
+
+fn by_extension(path: &Path) -> Result<Source> {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("bc") => Ok(Source::BC),
+        Some("ll") => Ok(Source::IR),
+        Some(ext) => Err(OinkieError::UnsupportedFormat(ext.to_string())),
+        None => Err(OinkieError::NoExtension(path.to_string_lossy().to_string())),
+    }
+}
+
+/// reads the first bytes of the file, or fewer bytes if the file is short or unreadable.
+fn read_head(path: &Path, len: usize) -> Vec<u8> {
+    let mut head = vec![];
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(len as u64).read_to_end(&mut head);
+    }
+    head
+}
+
 pub trait Extractor {
     fn btype(&self) -> BirthmarkType;
+    /// returns the length of the k-grams for the birthmark types with the arbitrary k.
+    fn k(&self) -> Option<usize> {
+        None
+    }
+    /// returns the maximum number of the skipped elements for the skip-gram birthmark types.
+    fn skip(&self) -> Option<usize> {
+        None
+    }
+    /// returns the form of the function names for the birthmark types of the function calls.
+    fn names(&self) -> Option<NameForm> {
+        None
+    }
+    /// returns the abstraction level of the instruction labels for the opcode birthmark types.
+    fn abstraction(&self) -> Option<Abstraction> {
+        None
+    }
+    /// returns the boundaries of the windows for the k-gram birthmark types.
+    fn boundary(&self) -> Option<Boundary> {
+        None
+    }
     fn visit(&mut self, module: &llvm_ir::Module, path: &PathBuf);
     fn visit_func(&mut self, func: &llvm_ir::Function);
     fn visit_bb(&mut self, bb: &llvm_ir::basicblock::BasicBlock);
@@ -118,6 +518,16 @@ pub trait Extractor {
     fn clear(&mut self);
 }
 
+/// builds the info of the birthmarks of the module, with the parameters of the extractor.
+fn new_info(extractor: &dyn Extractor, path: &Path, mode: Mode) -> Info {
+    Info::new(path.to_string_lossy().into(), path.to_path_buf(), extractor.btype(), mode)
+        .with_k(extractor.k())
+        .with_skip(extractor.skip())
+        .with_names(extractor.names())
+        .with_abstraction(extractor.abstraction())
+        .with_boundary(extractor.boundary())
+}
+
 struct FunctionModeExtractor {
     info: Option<Info>,
     delegates: Box<dyn Extractor>,
@@ -137,6 +547,26 @@ impl Extractor for FunctionModeExtractor {
     fn btype(&self) -> BirthmarkType {
         self.delegates.btype()
     }
+
+    fn k(&self) -> Option<usize> {
+        self.delegates.k()
+    }
+
+    fn skip(&self) -> Option<usize> {
+        self.delegates.skip()
+    }
+
+    fn names(&self) -> Option<NameForm> {
+        self.delegates.names()
+    }
+
+    fn abstraction(&self) -> Option<Abstraction> {
+        self.delegates.abstraction()
+    }
+
+    fn boundary(&self) -> Option<Boundary> {
+        self.delegates.boundary()
+    }
     fn visit_func(&mut self, func: &llvm_ir::Function) {
         self.delegates.visit_func(func);
         self.info = self.info.as_ref().map(|info| Info::new_from(&info, func.name.clone()));
@@ -157,7 +587,7 @@ impl Extractor for FunctionModeExtractor {
     }
     
     fn visit(&mut self, module: &llvm_ir::Module, path: &PathBuf) {
-        self.info = Some(Info::new(path.to_string_lossy().into(), path.clone(), self.btype(), Mode::Function));
+        self.info = Some(new_info(self, path, Mode::Function));
         self.delegates.visit(module, path);
     }
     
@@ -200,7 +630,7 @@ impl BBModeExtractor {
 impl Extractor for BBModeExtractor {
     fn visit(&mut self, module: &llvm_ir::Module, path: &PathBuf) {
         self.delegates.visit(module, path);
-        self.info = Some(Info::new(path.to_string_lossy().into(), path.clone(), self.btype(), Mode::BasicBlock));
+        self.info = Some(new_info(self, path, Mode::BasicBlock));
     }
 
     fn visit_func(&mut self, func: &llvm_ir::Function) {
@@ -244,6 +674,26 @@ impl Extractor for BBModeExtractor {
         self.delegates.btype()
     }
 
+    fn k(&self) -> Option<usize> {
+        self.delegates.k()
+    }
+
+    fn skip(&self) -> Option<usize> {
+        self.delegates.skip()
+    }
+
+    fn names(&self) -> Option<NameForm> {
+        self.delegates.names()
+    }
+
+    fn abstraction(&self) -> Option<Abstraction> {
+        self.delegates.abstraction()
+    }
+
+    fn boundary(&self) -> Option<Boundary> {
+        self.delegates.boundary()
+    }
+
     fn finish(&self) -> Result<Vec<Birthmark>> {
         Ok(self.birthmarks.clone())
     }
@@ -266,7 +716,7 @@ impl FileModeExtractor {
 impl Extractor for FileModeExtractor {
     fn visit(&mut self, module: &llvm_ir::Module, path: &PathBuf) {
         self.delegates.visit(module, path);
-        self.info = Some(Info::new(path.to_string_lossy().into(), path.clone(), self.btype(), Mode::File));
+        self.info = Some(new_info(self, path, Mode::File));
     }
 
     fn visit_func(&mut self, func: &llvm_ir::Function) {
@@ -308,25 +758,130 @@ impl Extractor for FileModeExtractor {
     fn btype(&self) -> BirthmarkType {
         self.delegates.btype()
     }
+
+    fn k(&self) -> Option<usize> {
+        self.delegates.k()
+    }
+
+    fn skip(&self) -> Option<usize> {
+        self.delegates.skip()
+    }
+
+    fn names(&self) -> Option<NameForm> {
+        self.delegates.names()
+    }
+
+    fn abstraction(&self) -> Option<Abstraction> {
+        self.delegates.abstraction()
+    }
+
+    fn boundary(&self) -> Option<Boundary> {
+        self.delegates.boundary()
+    }
     
     fn finish(&self) -> Result<Vec<Birthmark>> {
         Ok(self.birthmarks.clone())
     }
 }
 
-fn extract_birthmarks_impl(module: &llvm_ir::Module, extractor: &mut Box<dyn Extractor>, path: &PathBuf) -> Result<Vec<Birthmark>> {
-    extractor.visit(module, &path);
+fn extract_birthmarks_impl(module: &llvm_ir::Module, extractors: &mut [Box<dyn Extractor>], path: &PathBuf) -> Result<Vec<Vec<Birthmark>>> {
+    extractors.iter_mut().for_each(|e| e.visit(module, path));
     for func in &module.functions {
-        extractor.visit_func(func);
+        extractors.iter_mut().for_each(|e| e.visit_func(func));
         for bb in &func.basic_blocks {
-            extractor.visit_bb(bb);
+            extractors.iter_mut().for_each(|e| e.visit_bb(bb));
             for instr in &bb.instrs {
-                let _ = extractor.visit_inst(instr);
+                extractors.iter_mut().for_each(|e| { let _ = e.visit_inst(instr); });
             }
-            let _ = extractor.visit_bb_end(&bb.term);
+            extractors.iter_mut().for_each(|e| { let _ = e.visit_bb_end(&bb.term); });
         }
-        let _ = extractor.visit_func_end(func);
+        extractors.iter_mut().for_each(|e| { let _ = e.visit_func_end(func); });
     }
-    let _ = extractor.visit_end(module);
-    extractor.finish()
-}
\ No newline at end of file
+    extractors.iter_mut().for_each(|e| { let _ = e.visit_end(module); });
+    OinkieError::vec_result_to_result_vec(extractors.iter().map(|e| e.finish()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The function with a loop, shared with the tests of the extractors.
+    pub(super) const IR: &str = r#"
+        define i32 @f(i32 %n) {
+        entry:
+            %c = icmp sgt i32 %n, 0
+            br i1 %c, label %loop, label %exit
+        loop:
+            %i = phi i32 [ 0, %entry ], [ %j, %loop ]
+            %j = add i32 %i, 1
+            %d = icmp slt i32 %j, %n
+            br i1 %d, label %loop, label %exit
+        exit:
+            ret i32 0
+        }
+        "#;
+
+    #[test]
+    fn test_extract_many() {
+        let module = llvm_ir::Module::from_ir_str(IR).unwrap();
+        let types = [(BirthmarkType::CfgInv, Mode::Function), (BirthmarkType::OpSet, Mode::File), (BirthmarkType::OpSeq, Mode::BasicBlock)];
+        let groups = extract_many(&module, "<memory>", &types, &Options::default()).unwrap();
+        assert_eq!(groups.iter().map(|g| (g.btype.clone(), g.mode.clone())).collect::<Vec<_>>(), types);
+        assert_eq!(groups.iter().map(|g| g.birthmarks.len()).collect::<Vec<_>>(), vec![1, 1, 3]);
+        assert_eq!(groups[0].birthmarks[0].elements, extract(&module, "<memory>", &BirthmarkType::CfgInv, &Mode::Function).unwrap()[0].elements);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let expected = from_str(IR, &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        let birthmarks = from_bytes(IR.as_bytes(), &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        assert_eq!(birthmarks[0].elements, expected[0].elements);
+        assert_eq!(birthmarks[0].info.path, PathBuf::from("<bytes>"));
+        let birthmarks = from_reader(IR.as_bytes(), &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        assert_eq!(birthmarks[0].elements, expected[0].elements);
+        assert!(from_bytes(b"int main() { return 0; }", &BirthmarkType::CfgInv, &Mode::Function).is_err());
+
+        let bc = to_bitcode(IR);
+        let birthmarks = from_bytes(&bc, &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        assert_eq!(birthmarks[0].elements, expected[0].elements);
+        let wrapped = [&[0xde, 0xc0, 0x17, 0x0b][..], &0u32.to_le_bytes(), &20u32.to_le_bytes(), &(bc.len() as u32).to_le_bytes(), &7u32.to_le_bytes(), &bc].concat();
+        let birthmarks = from_reader(&wrapped[..], &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        assert_eq!(birthmarks[0].elements, expected[0].elements);
+    }
+
+    #[test]
+    fn test_from_compressed() {
+        use std::io::Write;
+        let gzip = |data: &[u8]| {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let expected = from_str(IR, &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        let birthmarks = from_bytes(&gzip(IR.as_bytes()), &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        assert_eq!(birthmarks[0].elements, expected[0].elements);
+        assert!(from_bytes(&gzip(&gzip(IR.as_bytes())), &BirthmarkType::CfgInv, &Mode::Function).is_err());
+    }
+
+    /// writes the textual IR into the bitcode with LLVM.
+    fn to_bitcode(ir: &str) -> Vec<u8> {
+        use llvm_sys::bit_writer::LLVMWriteBitcodeToMemoryBuffer;
+        use llvm_sys::core::*;
+        use llvm_sys::ir_reader::LLVMParseIRInContext;
+
+        // SAFETY: the IR buffer is consumed by the parser, and the other LLVM objects are disposed in this function.
+        unsafe {
+            let context = LLVMContextCreate();
+            let buffer = LLVMCreateMemoryBufferWithMemoryRangeCopy(ir.as_ptr() as *const _, ir.len(), c"<memory>".as_ptr());
+            let mut module = std::ptr::null_mut();
+            let mut message = std::ptr::null_mut();
+            assert_eq!(LLVMParseIRInContext(context, buffer, &mut module, &mut message), 0);
+            let bitcode = LLVMWriteBitcodeToMemoryBuffer(module);
+            let bytes = std::slice::from_raw_parts(LLVMGetBufferStart(bitcode) as *const u8, LLVMGetBufferSize(bitcode)).to_vec();
+            LLVMDisposeMemoryBuffer(bitcode);
+            LLVMDisposeModule(module);
+            LLVMContextDispose(context);
+            bytes
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use llvm_ir::{Name, Terminator};
+
+use crate::birthmarks::{BirthmarkType, Element};
+use crate::extractors::Extractor;
+use crate::extractors::opcodes::{instruction_to_str, terminator_to_str};
+use crate::graphs::{hash, Graph};
+use crate::Result;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum CfgKind {
+    /// graph invariants of each function.
+    Invariants,
+    /// Weisfeiler-Lehman hash of each function.
+    Hash,
+    /// Weisfeiler-Lehman subtree features of each basic block.
+    Wl,
+}
+
+/// extracts the birthmarks from the control flow graphs of functions,
+/// whose nodes are the basic blocks labeled by the multisets of their opcodes,
+/// and whose edges are the successors of the terminators.
+pub(super) struct CfgExtractor {
+    kind: CfgKind,
+    nodes: Vec<Node>,
+    elements: Vec<Element>,
+}
+
+struct Node {
+    name: Name,
+    label: String,
+    successors: Vec<Name>,
+}
+
+impl CfgExtractor {
+    pub fn new(kind: CfgKind) -> Self {
+        Self { kind, nodes: vec![], elements: vec![] }
+    }
+
+    fn features(&self, nodes: &[Node]) -> Vec<Element> {
+        let graph = graph(nodes);
+        match self.kind {
+            CfgKind::Invariants => vec![Element::Str(graph.invariants())],
+            CfgKind::Hash => vec![Element::Str(format!("{:016x}", graph.wl_hash()))],
+            CfgKind::Wl => graph.wl_features().into_iter()
+                .map(|h| Element::Str(format!("{:016x}", h)))
+                .collect(),
+        }
+    }
+}
+
+impl Extractor for CfgExtractor {
+    fn btype(&self) -> BirthmarkType {
+        match self.kind {
+            CfgKind::Invariants => BirthmarkType::CfgInv,
+            CfgKind::Hash => BirthmarkType::CfgHash,
+            CfgKind::Wl => BirthmarkType::CfgWl,
+        }
+    }
+
+    fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+    }
+
+    fn visit_func(&mut self, _func: &llvm_ir::Function) {
+        self.nodes.clear();
+    }
+
+    fn visit_bb(&mut self, bb: &llvm_ir::basicblock::BasicBlock) {
+        let mut opcodes = bb.instrs.iter()
+            .map(instruction_to_str)
+            .collect::<Vec<_>>();
+        opcodes.sort();
+        opcodes.push(terminator_to_str(&bb.term));
+        self.nodes.push(Node {
+            name: bb.name.clone(),
+            label: opcodes.join(" "),
+            successors: successors(&bb.term),
+        });
+    }
+
+    fn visit_inst(&mut self, _instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
+        Ok(None)
+    }
+
+    /// returns the features of the graph of the current basic block only.
+    fn visit_bb_end(&mut self, _term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        match self.nodes.last() {
+            Some(node) => {
+                let single = Node { name: node.name.clone(), label: node.label.clone(), successors: vec![] };
+                Ok(self.features(&[single]))
+            },
+            None => Ok(vec![]),
+        }
+    }
+
+    fn visit_func_end(&mut self, _func: &llvm_ir::Function) -> Result<Vec<Element>> {
+        if !self.nodes.is_empty() {
+            let features = self.features(&self.nodes);
+            self.elements.extend(features);
+            self.nodes.clear();
+        }
+        Ok(self.elements.clone())
+    }
+
+    fn visit_end(&mut self, _module: &llvm_ir::Module) -> Result<Vec<Element>> {
+        Ok(self.elements.clone())
+    }
+
+    fn finish(&self) -> Result<Vec<crate::birthmarks::Birthmark>> {
+        Ok(vec![])
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.elements.clear();
+    }
+}
+
+/// returns the names of the successor basic blocks of the terminator.
+pub(super) fn successors(term: &Terminator) -> Vec<Name> {
+    use Terminator::*;
+    match term {
+        Ret(_) | Resume(_) | Unreachable(_) => vec![],
+        Br(br) => vec![br.dest.clone()],
+        CondBr(br) => vec![br.true_dest.clone(), br.false_dest.clone()],
+        Switch(switch) => switch.dests.iter()
+            .map(|(_, name)| name.clone())
+            .chain(std::iter::once(switch.default_dest.clone()))
+            .collect(),
+        IndirectBr(br) => br.possible_dests.clone(),
+        Invoke(invoke) => vec![invoke.return_label.clone(), invoke.exception_label.clone()],
+        CleanupRet(ret) => ret.unwind_dest.iter().cloned().collect(),
+        CatchRet(ret) => vec![ret.successor.clone()],
+        CatchSwitch(switch) => switch.catch_handlers.iter()
+            .chain(switch.default_unwind_dest.iter())
+            .cloned()
+            .collect(),
+        CallBr(call) => vec![call.return_label.clone()],
+    }
+}
+
+/// builds the control flow graph of a function, the first node is the entry block.
+fn graph(nodes: &[Node]) -> Graph {
+    let index = nodes.iter().enumerate()
+        .map(|(i, n)| (&n.name, i))
+        .collect::<HashMap<_, _>>();
+    let succs = nodes.iter()
+        .map(|n| n.successors.iter().filter_map(|s| index.get(s).copied()).collect())
+        .collect();
+    let labels = nodes.iter()
+        .map(|n| hash(n.label.as_bytes(), &[]))
+        .collect();
+    Graph::new(labels, succs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::extractors::{extract, Mode};
+    use crate::extractors::tests::IR;
+
+    use super::*;
+
+    #[test]
+    fn test_invariants() {
+        let module = llvm_ir::Module::from_ir_str(IR).unwrap();
+        let birthmarks = extract(&module, PathBuf::from("<memory>"), &BirthmarkType::CfgInv, &Mode::Function).unwrap();
+        assert_eq!(birthmarks.len(), 1);
+        assert_eq!(birthmarks[0].elements, vec![
+            Element::Str("nodes=3 edges=4 exits=1 back_edges=1 max_out=2 max_in=2 depth=1".into()),
+        ]);
+    }
+
+    #[test]
+    fn test_wl() {
+        let module = llvm_ir::Module::from_ir_str(IR).unwrap();
+        let birthmarks = extract(&module, PathBuf::from("<memory>"), &BirthmarkType::CfgWl, &Mode::File).unwrap();
+        assert_eq!(birthmarks[0].elements.len(), 3 * (crate::graphs::WL_ITERATIONS + 1));
+    }
+}
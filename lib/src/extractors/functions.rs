@@ -1,19 +1,112 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use llvm_ir::{Constant, Name};
+use clap::ValueEnum;
+use llvm_ir::instruction::InlineAssembly;
+use llvm_ir::{Constant, ConstantRef, Name, Operand, TypeRef};
 use either::Either;
 
 use crate::birthmarks::Element;
 use crate::extractors::Extractor;
 use crate::Result;
 
+/// The form of the function names in the call birthmarks.
+/// The forms except `raw` demangle the Itanium C++, Rust legacy, and Rust v0 symbols,
+/// and strip the crate hashes and the generic arguments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum NameForm {
+    /// the symbol names as they are in the IR.
+    #[default]
+    Raw,
+    /// the demangled full paths, such as `std::io::stdio::_print`.
+    Full,
+    /// the last segments of the demangled paths, such as `_print`.
+    Last,
+    /// the namespaces of the demangled paths, such as `std::io::stdio`.
+    /// The names without the namespaces are left as they are.
+    Namespace,
+}
+
+impl NameForm {
+    /// normalizes the symbol name into this form.
+    pub fn normalize(&self, symbol: &str) -> String {
+        if *self == NameForm::Raw {
+            return symbol.to_string();
+        }
+        let path = demangle(symbol);
+        let segments = split_path(&path);
+        match (self, segments.split_last()) {
+            (NameForm::Last, Some((last, _))) => last.to_string(),
+            (NameForm::Namespace, Some((_, namespace))) if !namespace.is_empty() => namespace.join("::"),
+            _ => path,
+        }
+    }
+}
+
+/// demangles the symbol without the hashes, the parameters, and the generic arguments.
+/// The symbols not mangled are returned as they are.
+fn demangle(symbol: &str) -> String {
+    let symbol = symbol.split(".llvm.").next().unwrap_or(symbol);
+    if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+        return strip_generics(&format!("{:#}", demangled));
+    }
+    let options = cpp_demangle::DemangleOptions::new().no_params().no_return_type();
+    match cpp_demangle::Symbol::new(symbol).ok().and_then(|s| s.demangle_with_options(&options).ok()) {
+        Some(demangled) => strip_generics(&demangled),
+        None => symbol.to_string(),
+    }
+}
+
+/// removes the generic (template) arguments following the identifiers, such as `Vec<T>` and `foo::<T>`.
+/// The qualified paths, such as `<T as Trait>::method`, are kept.
+/// The name is returned as it is if the brackets are unbalanced, such as `operator<`.
+fn strip_generics(name: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    for c in name.chars() {
+        match c {
+            '<' if depth > 0 || result.ends_with(|p: char| p.is_alphanumeric() || p == '_' || p == ':') => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth > 0 => (),
+            _ => result.push(c),
+        }
+    }
+    if depth != 0 {
+        return name.to_string();
+    }
+    result.replace("::::", "::").trim_end_matches("::").to_string()
+}
+
+/// splits the path by `::` outside of the angle brackets.
+fn split_path(path: &str) -> Vec<&str> {
+    let mut segments = vec![];
+    let (mut depth, mut start) = (0i32, 0);
+    let bytes = path.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' => depth += 1,
+            b'>' => depth -= 1,
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                segments.push(&path[start..i]);
+                start = i + 2;
+                i += 1;
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
 pub(super) struct SeqNames {
+    form: NameForm,
     names: Vec<Element>,
 }
 
 impl SeqNames {
-    pub fn new() -> Self {
-        Self { names: vec![] }
+    pub fn new(form: NameForm) -> Self {
+        Self { form, names: vec![] }
     }
 }
 
@@ -22,6 +115,10 @@ impl Extractor for SeqNames {
         crate::birthmarks::BirthmarkType::Sfc
     }
 
+    fn names(&self) -> Option<NameForm> {
+        Some(self.form)
+    }
+
     fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
     }
 
@@ -33,7 +130,7 @@ impl Extractor for SeqNames {
 
     fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
         if let llvm_ir::Instruction::Call(call) = instr {
-            if let Some(fname) = extract_called_name(&call) {
+            if let Some(fname) = extract_called_name(&call, self.form) {
                 let r = Element::Str(fname.clone());
                 self.names.push(r.clone());
                 Ok(Some(r))
@@ -45,7 +142,10 @@ impl Extractor for SeqNames {
         }
     }
 
-    fn visit_bb_end(&mut self, _term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+    fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        if let Some(fname) = extract_called_name_from_term(term, self.form) {
+            self.names.push(Element::Str(fname));
+        }
         Ok(self.names.clone())
     }
 
@@ -67,12 +167,13 @@ impl Extractor for SeqNames {
 }
 
 pub(super) struct FreqNames {
+    form: NameForm,
     freq: HashMap<String, usize>,
 }
 
 impl FreqNames {
-    pub fn new() -> Self {
-        Self { freq: HashMap::new() }
+    pub fn new(form: NameForm) -> Self {
+        Self { form, freq: HashMap::new() }
     }
 }
 
@@ -81,6 +182,10 @@ impl Extractor for FreqNames {
         crate::birthmarks::BirthmarkType::Ffc
     }
 
+    fn names(&self) -> Option<NameForm> {
+        Some(self.form)
+    }
+
     fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
     }
 
@@ -92,7 +197,7 @@ impl Extractor for FreqNames {
 
     fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
         if let llvm_ir::Instruction::Call(call) = instr {
-            if let Some(fname) = extract_called_name(&call) {
+            if let Some(fname) = extract_called_name(&call, self.form) {
                 *self.freq.entry(fname.clone()).or_insert(0) += 1;
                 Ok(Some(Element::Freq(*self.freq.get(&fname).unwrap(), fname.clone())))
             } else {
@@ -103,7 +208,10 @@ impl Extractor for FreqNames {
         }
     }
 
-    fn visit_bb_end(&mut self, _term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+    fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        if let Some(fname) = extract_called_name_from_term(term, self.form) {
+            *self.freq.entry(fname).or_insert(0) += 1;
+        }
         Ok(self.freq.clone().into_iter().map(|(e, i)| Element::Freq(i, e)).collect())
     }
 
@@ -124,64 +232,186 @@ impl Extractor for FreqNames {
     }
 }
 
-fn extract_called_name(call: &llvm_ir::instruction::Call) -> Option<String> {
-    match &call.function {
-        Either::Left(assembly) => {
-            match &assembly.ty.as_ref() {
-                _ => None,
-                // llvm_ir::Type::VoidType => todo!(),
-                // llvm_ir::Type::IntegerType { bits } => todo!(),
-                // llvm_ir::Type::PointerType { addr_space } => todo!(),
-                // llvm_ir::Type::FPType(fptype) => todo!(),
-                // llvm_ir::Type::FuncType { result_type, param_types, is_var_arg } => todo!(),
-                // llvm_ir::Type::VectorType { element_type, num_elements, scalable } => todo!(),
-                // llvm_ir::Type::ArrayType { element_type, num_elements } => todo!(),
-                // llvm_ir::Type::StructType { element_types, is_packed } => todo!(),
-                // llvm_ir::Type::NamedStructType { name } => todo!(),
-                // llvm_ir::Type::X86_MMXType => todo!(),
-                // llvm_ir::Type::X86_AMXType => todo!(),
-                // llvm_ir::Type::MetadataType => todo!(),
-                // llvm_ir::Type::LabelType => todo!(),
-                // llvm_ir::Type::TokenType => todo!(),
-                // llvm_ir::Type::TargetExtType => todo!(),
-            }
-        },
-        Either::Right(llvm_ir::Operand::ConstantOperand(c)) => match c.as_ref() {
-            Constant::GlobalReference { name, ..} => match name {
-                Name::Name(n) => Some(n.as_str().to_string()),
-                Name::Number(_n) => None,
-            },
-            _ => None,
-            // Constant::Int { bits, value } => None,
-            // Constant::Float(f) => None,
-            // Constant::Null(type_ref) => todo!(),
-            // Constant::AggregateZero(type_ref) => todo!(),
-            // Constant::Struct { name, values, is_packed } => todo!(),
-            // Constant::Array { element_type, elements } => todo!(),
-            // Constant::Vector(constant_refs) => todo!(),
-            // Constant::Undef(type_ref) => todo!(),
-            // Constant::Poison(type_ref) => todo!(),
-            // Constant::BlockAddress => todo!(),
-            // Constant::TokenNone => todo!(),
-            // Constant::PtrAuth { ptr, key, disc, addr_disc } => todo!(),
-            // Constant::Add(add) => todo!(),
-            // Constant::Sub(sub) => todo!(),
-            // Constant::Mul(mul) => todo!(),
-            // Constant::Xor(xor) => todo!(),
-            // Constant::ExtractElement(extract_element) => todo!(),
-            // Constant::InsertElement(insert_element) => todo!(),
-            // Constant::ShuffleVector(shuffle_vector) => todo!(),
-            // Constant::GetElementPtr(get_element_ptr) => todo!(),
-            // Constant::Trunc(trunc) => todo!(),
-            // Constant::PtrToInt(ptr_to_int) => todo!(),
-            // Constant::IntToPtr(int_to_ptr) => todo!(),
-            // Constant::BitCast(bit_cast) => todo!(),
-            // Constant::AddrSpaceCast(addr_space_cast) => todo!(),
+pub(super) struct CallGraph {
+    form: NameForm,
+    caller: String,
+    edges: Vec<Element>,
+    seen: HashSet<Element>,
+}
+
+impl CallGraph {
+    pub fn new(form: NameForm) -> Self {
+        Self { form, caller: String::new(), edges: vec![], seen: HashSet::new() }
+    }
+
+    /// records the edge from the current function to the callee, and returns it if it is new.
+    fn push(&mut self, callee: Option<String>) -> Option<Element> {
+        let edge = Element::Kgram(vec![self.caller.clone(), callee?]);
+        if self.seen.insert(edge.clone()) {
+            self.edges.push(edge.clone());
+            Some(edge)
+        } else {
+            None
+        }
+    }
+}
+
+impl Extractor for CallGraph {
+    fn btype(&self) -> crate::birthmarks::BirthmarkType {
+        crate::birthmarks::BirthmarkType::CallGraph
+    }
+
+    fn names(&self) -> Option<NameForm> {
+        Some(self.form)
+    }
+
+    fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+    }
+
+    fn visit_func(&mut self, func: &llvm_ir::Function) {
+        self.caller = self.form.normalize(&func.name);
+    }
+
+    fn visit_bb(&mut self, _bb: &llvm_ir::basicblock::BasicBlock) {
+    }
+
+    fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
+        if let llvm_ir::Instruction::Call(call) = instr {
+            Ok(self.push(extract_called_name(call, self.form)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        self.push(extract_called_name_from_term(term, self.form));
+        Ok(self.edges.clone())
+    }
+
+    fn visit_func_end(&mut self, _func: &llvm_ir::Function) -> Result<Vec<Element>> {
+        Ok(self.edges.clone())
+    }
+
+    fn visit_end(&mut self, _module: &llvm_ir::Module) -> Result<Vec<Element>> {
+        Ok(self.edges.clone())
+    }
+
+    fn finish(&self) -> Result<Vec<crate::birthmarks::Birthmark>> {
+        Ok(vec![])
+    }
+
+    fn clear(&mut self) {
+        self.edges.clear();
+        self.seen.clear();
+    }
+}
+
+pub(super) fn extract_called_name(call: &llvm_ir::instruction::Call, form: NameForm) -> Option<String> {
+    extract_callee_name(&call.function, Some(&call.function_ty), form)
+}
+
+/// returns the callee name of the terminators calling functions (`invoke` and `callbr`).
+pub(super) fn extract_called_name_from_term(term: &llvm_ir::Terminator, form: NameForm) -> Option<String> {
+    match term {
+        llvm_ir::Terminator::Invoke(invoke) => extract_callee_name(&invoke.function, Some(&invoke.function_ty), form),
+        llvm_ir::Terminator::CallBr(call_br) => extract_callee_name(&call_br.function, None, form),
+        _ => None,
+    }
+}
+
+/// returns the name of the called function.
+/// The indirect calls are labeled by the function types, such as `indirect i32 (ptr)`,
+/// and the calls of inline assemblies are ignored.
+fn extract_callee_name(function: &Either<InlineAssembly, Operand>, function_ty: Option<&TypeRef>, form: NameForm) -> Option<String> {
+    match function {
+        Either::Left(_assembly) => None,
+        Either::Right(Operand::ConstantOperand(c)) => match extract_constant_name(c) {
+            Some(name) => Some(form.normalize(&name)),
+            None => Some(indirect_label(function_ty)),
         },
-        Either::Right(llvm_ir::Operand::LocalOperand { name, .. }) => match name {
+        Either::Right(Operand::LocalOperand { .. }) => Some(indirect_label(function_ty)),
+        Either::Right(Operand::MetadataOperand) => None,
+    }
+}
+
+/// resolves the function name through the constant expressions, such as bitcasts.
+pub(super) fn extract_constant_name(c: &ConstantRef) -> Option<String> {
+    match c.as_ref() {
+        Constant::GlobalReference { name, ..} => match name {
             Name::Name(n) => Some(n.as_str().to_string()),
-            Name::Number(_n) => None,
+            Name::Number(n) => Some(n.to_string()),
         },
+        Constant::BitCast(cast) => extract_constant_name(&cast.operand),
+        Constant::AddrSpaceCast(cast) => extract_constant_name(&cast.operand),
+        Constant::GetElementPtr(gep) => extract_constant_name(&gep.address),
         _ => None,
     }
 }
+
+fn indirect_label(function_ty: Option<&TypeRef>) -> String {
+    match function_ty {
+        Some(ty) => format!("indirect {}", ty),
+        None => "indirect".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::birthmarks::BirthmarkType;
+    use crate::extractors::{extract, extract_with, Mode, Options};
+
+    use super::*;
+
+    #[test]
+    fn test_invoke_and_indirect_calls() {
+        let ir = r#"
+        declare i32 @__gxx_personality_v0(...)
+        declare void @may_throw()
+        declare i32 @puts(ptr)
+
+        define void @f(ptr %fp) personality ptr @__gxx_personality_v0 {
+        entry:
+            call void %fp(i32 1)
+            %r = call i32 @puts(ptr null)
+            invoke void @may_throw() to label %ok unwind label %lpad
+        ok:
+            ret void
+        lpad:
+            %l = landingpad { ptr, i32 } cleanup
+            resume { ptr, i32 } %l
+        }
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let birthmarks = extract(&module, PathBuf::from("<memory>"), &BirthmarkType::Sfc, &Mode::File).unwrap();
+        assert_eq!(birthmarks[0].elements, vec![
+            Element::Str("indirect void (i32)".into()),
+            Element::Str("puts".into()),
+            Element::Str("may_throw".into()),
+        ]);
+        assert_eq!(birthmarks[0].info.names, Some(NameForm::Raw));
+
+        let opts = Options { names: NameForm::Last, ..Options::default() };
+        let last = extract_with(&module, PathBuf::from("<memory>"), &BirthmarkType::Sfc, &Mode::File, &opts).unwrap();
+        assert!(!last[0].info.is_same_type(&birthmarks[0].info));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let cpp = "_ZN5space3fooIiEEvT_";
+        assert_eq!(NameForm::Raw.normalize(cpp), cpp);
+        assert_eq!(NameForm::Full.normalize(cpp), "space::foo");
+        assert_eq!(NameForm::Last.normalize("_ZNSt6vectorIiSaIiEE9push_backERKi"), "push_back");
+        assert_eq!(NameForm::Namespace.normalize("_ZNSt6vectorIiSaIiEE9push_backERKi"), "std::vector");
+
+        let legacy = "_ZN3std2io5stdio6_print17h0123456789abcdefE";
+        assert_eq!(NameForm::Full.normalize(legacy), "std::io::stdio::_print");
+        let v0 = "_RINvNtCs1234_4core3mem7drop_inNtCs5678_5alloc6StringEB4_";
+        assert_eq!(NameForm::Full.normalize(v0), "core::mem::drop_in");
+        assert_eq!(NameForm::Namespace.normalize(v0), "core::mem");
+
+        assert_eq!(NameForm::Full.normalize("printf"), "printf");
+        assert_eq!(NameForm::Namespace.normalize("printf"), "printf");
+        assert_eq!(NameForm::Last.normalize("<T as core::fmt::Display>::fmt"), "fmt");
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use llvm_ir::constant::Float;
+use llvm_ir::{Constant, ConstantRef, Name, Type};
+
+use crate::birthmarks::{BirthmarkType, Element, Fnv};
+use crate::extractors::Extractor;
+use crate::extractors::operands::{decode_string, signed};
+use crate::Result;
+
+/// extracts the data of the global variables, which copied programs tend to keep,
+/// such as the format strings, the messages, and the lookup tables.
+/// The `i8` arrays are decoded into the strings (`str "hello"`),
+/// and the other arrays into the flattened value sequences (`table i32 [1, -2, 3]`).
+/// The elements are extracted from the whole module, so the function and the basic block modes have no elements.
+pub(super) struct GlobalDataExtractor {
+    min_string_length: usize,
+    max_table_length: usize,
+    elements: Vec<Element>,
+}
+
+impl GlobalDataExtractor {
+    /// The strings shorter than `min_string_length` characters are ignored,
+    /// and the tables longer than `max_table_length` values are hashed (`table i32 [#0123456789abcdef; 300]`).
+    pub fn new(min_string_length: usize, max_table_length: usize) -> Self {
+        Self { min_string_length, max_table_length, elements: vec![] }
+    }
+
+    fn table(&self, element_type: &Type, values: Vec<String>) -> Element {
+        if values.len() > self.max_table_length {
+            let mut hasher = Fnv::new();
+            values.iter().for_each(|v| { hasher.write(v.as_bytes()); hasher.write(b","); });
+            Element::Str(format!("table {} [#{:016x}; {}]", element_type, hasher.finish(), values.len()))
+        } else {
+            Element::Str(format!("table {} [{}]", element_type, values.join(", ")))
+        }
+    }
+}
+
+impl Extractor for GlobalDataExtractor {
+    fn btype(&self) -> BirthmarkType {
+        BirthmarkType::GlobalData
+    }
+
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        let strings = module.global_vars.iter()
+            .filter_map(|g| g.initializer.as_ref()
+                .and_then(decode_string)
+                .map(|s| (g.name.clone(), s)))
+            .collect::<HashMap<_, _>>();
+        self.elements.clear();
+        for initializer in module.global_vars.iter().filter_map(|g| g.initializer.as_ref()) {
+            if let Some(s) = decode_string(initializer) {
+                if s.chars().count() >= self.min_string_length {
+                    self.elements.push(Element::Str(format!("str {:?}", s)));
+                }
+            } else if let Constant::Array { element_type, elements } = initializer.as_ref() {
+                let mut values = vec![];
+                elements.iter().for_each(|e| normalize(e, &strings, &mut values));
+                let table = self.table(element_type, values);
+                self.elements.push(table);
+            }
+        }
+    }
+
+    fn visit_func(&mut self, _func: &llvm_ir::Function) {
+    }
+
+    fn visit_bb(&mut self, _bb: &llvm_ir::basicblock::BasicBlock) {
+    }
+
+    fn visit_inst(&mut self, _instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
+        Ok(None)
+    }
+
+    fn visit_bb_end(&mut self, _term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        Ok(vec![])
+    }
+
+    fn visit_func_end(&mut self, _func: &llvm_ir::Function) -> Result<Vec<Element>> {
+        Ok(vec![])
+    }
+
+    fn visit_end(&mut self, _module: &llvm_ir::Module) -> Result<Vec<Element>> {
+        Ok(self.elements.clone())
+    }
+
+    fn finish(&self) -> Result<Vec<crate::birthmarks::Birthmark>> {
+        Ok(vec![])
+    }
+
+    fn clear(&mut self) {
+        self.elements.clear();
+    }
+}
+
+/// flattens the constant into the values independent of the symbol names.
+/// The references to the strings become the quoted strings, and the other references become `ptr`.
+fn normalize(c: &ConstantRef, strings: &HashMap<Name, String>, values: &mut Vec<String>) {
+    match c.as_ref() {
+        Constant::Int { bits, value } => values.push(signed(*bits, *value)),
+        Constant::Float(Float::Single(f)) => values.push(f.to_string()),
+        Constant::Float(Float::Double(f)) => values.push(f.to_string()),
+        Constant::Null(_) => values.push("null".to_string()),
+        Constant::GlobalReference { name, .. } => match strings.get(name) {
+            Some(s) => values.push(format!("{:?}", s)),
+            None => values.push("ptr".to_string()),
+        },
+        Constant::Array { elements, .. } | Constant::Struct { values: elements, .. } | Constant::Vector(elements) =>
+            elements.iter().for_each(|e| normalize(e, strings, values)),
+        Constant::GetElementPtr(gep) => normalize(&gep.address, strings, values),
+        Constant::BitCast(cast) => normalize(&cast.operand, strings, values),
+        Constant::AddrSpaceCast(cast) => normalize(&cast.operand, strings, values),
+        _ => values.push("_".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::extractors::{extract_with, Mode, Options};
+
+    use super::*;
+
+    #[test]
+    fn test_global_data() {
+        let ir = r#"
+        @.str = private unnamed_addr constant [6 x i8] c"hello\00", align 1
+        @.str.1 = private unnamed_addr constant [3 x i8] c"%d\00", align 1
+        @names = global [2 x ptr] [ptr @.str, ptr null], align 8
+        @table = constant [4 x i16] [i16 -1, i16 7, i16 0, i16 3], align 2
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let opts = Options { min_string_length: 4, max_table_length: 3, ..Options::default() };
+        let birthmarks = extract_with(&module, PathBuf::from("<memory>"), &BirthmarkType::GlobalData, &Mode::File, &opts).unwrap();
+        let elements = &birthmarks[0].elements;
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0], Element::Str("str \"hello\"".into()));
+        assert_eq!(elements[1], Element::Str("table ptr [\"hello\", null]".into()));
+        assert!(matches!(&elements[2], Element::Str(s) if s.starts_with("table i16 [#") && s.ends_with("; 4]")));
+    }
+}
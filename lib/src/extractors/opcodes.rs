@@ -1,27 +1,197 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-use crate::birthmarks::Element;
-use crate::extractors::Extractor;
+use clap::ValueEnum;
+use either::Either;
+use llvm_ir::types::Types;
+use llvm_ir::{Instruction, Name, Operand, Terminator, Type, TypeRef};
+
+use crate::birthmarks::{BirthmarkType, Element};
+use crate::extractors::{Extractor, Mode};
+use crate::extractors::cfg::successors;
+use crate::extractors::functions::{extract_called_name, extract_called_name_from_term, extract_constant_name, NameForm};
 use crate::Result;
 
+/// The abstraction level of the instruction labels in the opcode birthmarks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum Abstraction {
+    /// the bare opcodes, such as `ICmp`.
+    #[default]
+    Opcode,
+    /// the opcodes with the type classes of the results, such as `ICmp int`.
+    /// The comparisons and the stores are labeled by the type classes of their operands.
+    Typed,
+    /// the typed opcodes with the details of the operands,
+    /// such as the predicates (`ICmp int sgt`), the call targets (`Call void direct`),
+    /// the alignments and the volatility (`Load int align4 volatile`), and the cast widths (`ZExt i8 i32`).
+    Operand,
+}
+
+/// The boundaries which the windows of the k-grams do not cross.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum Boundary {
+    /// the windows slide over the whole module, across the functions.
+    Module,
+    /// the windows are reset at the start of each function.
+    #[default]
+    Function,
+    /// the windows are reset at the start of each basic block.
+    BasicBlock,
+    /// the windows follow the successor edges of the control flow graphs, which yields the k-grams of the paths.
+    /// The k-grams are emitted at the end of each function, or in the blocks in the basic block mode.
+    /// The skip-grams treat this as [`Boundary::Function`].
+    Path,
+}
+
+/// makes the labels of the instructions in the given abstraction level.
+/// The labels other than the bare opcodes need the types of the module given by [`Labeler::visit`].
+struct Labeler {
+    abstraction: Abstraction,
+    types: Option<Types>,
+}
+
+impl Labeler {
+    fn new(abstraction: Abstraction) -> Self {
+        Self { abstraction, types: None }
+    }
+
+    fn visit(&mut self, module: &llvm_ir::Module) {
+        if self.abstraction != Abstraction::Opcode {
+            self.types = Some(module.types.clone());
+        }
+    }
+
+    fn instruction(&self, inst: &Instruction) -> String {
+        let opcode = instruction_to_str(inst);
+        match (&self.types, self.abstraction) {
+            (None, _) | (_, Abstraction::Opcode) => opcode,
+            (Some(types), Abstraction::Typed) => format!("{} {}", opcode, type_class(&instruction_type(inst, types))),
+            (Some(types), Abstraction::Operand) => {
+                let class = type_class(&instruction_type(inst, types));
+                match operand_details(inst, types) {
+                    Some(details) => format!("{} {} {}", opcode, class, details),
+                    None => format!("{} {}", opcode, class),
+                }
+            },
+        }
+    }
+
+    fn terminator(&self, term: &Terminator) -> String {
+        let opcode = terminator_to_str(term);
+        match (&self.types, self.abstraction, term) {
+            (None, _, _) | (_, Abstraction::Opcode, _) => opcode,
+            (Some(types), _, Terminator::Ret(ret)) => match &ret.return_operand {
+                Some(operand) => format!("{} {}", opcode, type_class(&types.type_of(operand))),
+                None => format!("{} void", opcode),
+            },
+            (Some(_), Abstraction::Operand, Terminator::Invoke(invoke)) => format!("{} {}", opcode, call_target(&invoke.function)),
+            (Some(_), Abstraction::Operand, Terminator::CallBr(call_br)) => format!("{} {}", opcode, call_target(&call_br.function)),
+            _ => opcode,
+        }
+    }
+}
+
+/// returns the type of the result, or the type of the operands for the comparisons and the stores.
+fn instruction_type(inst: &Instruction, types: &Types) -> TypeRef {
+    match inst {
+        Instruction::ICmp(cmp) => types.type_of(&cmp.operand0),
+        Instruction::FCmp(cmp) => types.type_of(&cmp.operand0),
+        Instruction::Store(store) => types.type_of(&store.value),
+        _ => types.type_of(inst),
+    }
+}
+
+fn type_class(ty: &TypeRef) -> &'static str {
+    match ty.as_ref() {
+        Type::VoidType => "void",
+        Type::IntegerType { .. } => "int",
+        Type::PointerType { .. } => "ptr",
+        Type::FPType(_) => "fp",
+        Type::FuncType { .. } => "func",
+        Type::VectorType { .. } => "vector",
+        Type::ArrayType { .. } => "array",
+        Type::StructType { .. } | Type::NamedStructType { .. } => "struct",
+        Type::LabelType => "label",
+        Type::TokenType => "token",
+        Type::MetadataType => "metadata",
+        _ => "other",
+    }
+}
+
+/// returns the operand details of the instruction for [`Abstraction::Operand`].
+fn operand_details(inst: &Instruction, types: &Types) -> Option<String> {
+    use Instruction::*;
+    let cast = |operand: &Operand, to_type: &TypeRef| Some(format!("{} {}", types.type_of(operand), to_type));
+    match inst {
+        ICmp(cmp) => Some(cmp.predicate.to_string()),
+        FCmp(cmp) => Some(cmp.predicate.to_string()),
+        Call(call) => Some(call_target(&call.function).to_string()),
+        Load(load) => Some(memory_access(load.alignment, load.volatile, load.atomicity.is_some())),
+        Store(store) => Some(memory_access(store.alignment, store.volatile, store.atomicity.is_some())),
+        Trunc(c) => cast(&c.operand, &c.to_type),
+        ZExt(c) => cast(&c.operand, &c.to_type),
+        SExt(c) => cast(&c.operand, &c.to_type),
+        FPToUI(c) => cast(&c.operand, &c.to_type),
+        FPToSI(c) => cast(&c.operand, &c.to_type),
+        UIToFP(c) => cast(&c.operand, &c.to_type),
+        SIToFP(c) => cast(&c.operand, &c.to_type),
+        FPTrunc(c) => cast(&c.operand, &c.to_type),
+        FPExt(c) => cast(&c.operand, &c.to_type),
+        PtrToInt(c) => cast(&c.operand, &c.to_type),
+        IntToPtr(c) => cast(&c.operand, &c.to_type),
+        BitCast(c) => cast(&c.operand, &c.to_type),
+        AddrSpaceCast(c) => cast(&c.operand, &c.to_type),
+        _ => None,
+    }
+}
+
+fn memory_access(alignment: u32, volatile: bool, atomic: bool) -> String {
+    let mut label = format!("align{}", alignment);
+    if volatile {
+        label.push_str(" volatile");
+    }
+    if atomic {
+        label.push_str(" atomic");
+    }
+    label
+}
+
+/// returns the kind of the call target: `asm`, `intrinsic`, `direct`, or `indirect`.
+fn call_target(function: &Either<llvm_ir::instruction::InlineAssembly, Operand>) -> &'static str {
+    match function {
+        Either::Left(_assembly) => "asm",
+        Either::Right(Operand::ConstantOperand(c)) => match extract_constant_name(c) {
+            Some(name) if name.starts_with("llvm.") => "intrinsic",
+            Some(_) => "direct",
+            None => "indirect",
+        },
+        Either::Right(_) => "indirect",
+    }
+}
+
 pub(super) struct SeqExtractor {
+    labeler: Labeler,
     opcodes: Vec<Element>,
 }
 
 impl SeqExtractor {
-    pub fn new() -> Self {
-        Self { opcodes: vec![] }
+    pub fn new(abstraction: Abstraction) -> Self {
+        Self { labeler: Labeler::new(abstraction), opcodes: vec![] }
     }
 }
 
 impl Extractor for SeqExtractor {
-    fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        self.labeler.visit(module);
     }
 
     fn btype(&self) -> crate::birthmarks::BirthmarkType {
         crate::birthmarks::BirthmarkType::OpSeq
     }
 
+    fn abstraction(&self) -> Option<Abstraction> {
+        Some(self.labeler.abstraction)
+    }
+
     fn visit_func(&mut self, _func: &llvm_ir::Function) {
     }
     
@@ -29,13 +199,13 @@ impl Extractor for SeqExtractor {
     }
     
     fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
-        let opcode = Element::Str(instruction_to_str(instr));
+        let opcode = Element::Str(self.labeler.instruction(instr));
         self.opcodes.push(opcode.clone());
         Ok(Some(opcode))
     }
     
     fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
-        let opcode = Element::Str(terminator_to_str(term));
+        let opcode = Element::Str(self.labeler.terminator(term));
         self.opcodes.push(opcode.clone());
         Ok(self.opcodes.clone())
     }
@@ -58,22 +228,28 @@ impl Extractor for SeqExtractor {
 }
 
 pub(super) struct SetExtractor {
+    labeler: Labeler,
     opcodes: HashSet<Element>,
 }
 
 impl SetExtractor {
-    pub fn new() -> Self {
-        Self { opcodes: HashSet::new() }
+    pub fn new(abstraction: Abstraction) -> Self {
+        Self { labeler: Labeler::new(abstraction), opcodes: HashSet::new() }
     }
 }
 
 impl Extractor for SetExtractor {
-    fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        self.labeler.visit(module);
     }
 
     fn btype(&self) -> crate::birthmarks::BirthmarkType {
         crate::birthmarks::BirthmarkType::OpSet
     }
+
+    fn abstraction(&self) -> Option<Abstraction> {
+        Some(self.labeler.abstraction)
+    }
     
     fn visit_func(&mut self, _func: &llvm_ir::Function) {
     }
@@ -82,13 +258,13 @@ impl Extractor for SetExtractor {
     }
     
     fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
-        let opcode = Element::Str(instruction_to_str(instr));
+        let opcode = Element::Str(self.labeler.instruction(instr));
         self.opcodes.insert(opcode.clone());
         Ok(Some(opcode))
     }
     
     fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
-        let opcode = Element::Str(terminator_to_str(term));
+        let opcode = Element::Str(self.labeler.terminator(term));
         self.opcodes.insert(opcode.clone());
         Ok(self.opcodes.clone().into_iter().collect())
     }
@@ -111,12 +287,13 @@ impl Extractor for SetExtractor {
 }
 
 pub(super) struct FreqExtractor {
+    labeler: Labeler,
     opcodes: HashMap<String, usize>,
 }
 
 impl FreqExtractor {
-    pub fn new() -> Self {
-        Self { opcodes: HashMap::new() }
+    pub fn new(abstraction: Abstraction) -> Self {
+        Self { labeler: Labeler::new(abstraction), opcodes: HashMap::new() }
     }
 }
 
@@ -125,7 +302,12 @@ impl Extractor for FreqExtractor {
         crate::birthmarks::BirthmarkType::OpFreq
     }
 
-    fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+    fn abstraction(&self) -> Option<Abstraction> {
+        Some(self.labeler.abstraction)
+    }
+
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        self.labeler.visit(module);
     }
 
     fn visit_func(&mut self, _func: &llvm_ir::Function) {
@@ -135,14 +317,14 @@ impl Extractor for FreqExtractor {
     }
 
     fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
-        let opcode = instruction_to_str(instr);
+        let opcode = self.labeler.instruction(instr);
         *self.opcodes.entry(opcode.clone()).or_insert(0) += 1;
         let v = *self.opcodes.get(&opcode).unwrap_or(&0);
         Ok(Some(Element::Freq(v, opcode)))
     }
 
     fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
-        let opcode = terminator_to_str(term);
+        let opcode = self.labeler.terminator(term);
         *self.opcodes.entry(opcode.clone()).or_insert(0) += 1;
         Ok(self.opcodes.clone().into_iter().map(|(e, i)| Element::Freq(i, e)).collect())
     }
@@ -164,22 +346,39 @@ impl Extractor for FreqExtractor {
     }
 }
 
+/// The element stream which the k-grams slide over.
+enum Stream {
+    /// the instruction labels in the given abstraction level.
+    Opcodes(Box<Labeler>),
+    /// the callee names in the given form.
+    Calls(NameForm),
+}
+
 pub(super) struct KGramExtractor {
+    btype: BirthmarkType,
+    stream: Stream,
+    boundary: Boundary,
+    /// whether the k-grams of the paths are also made in each block, for the basic block mode.
+    block_windows: bool,
     n: usize,
     current: KGram,
     kgrams: Vec<KGram>,
+    blocks: Vec<Block>,
 }
 
-impl KGramExtractor {
-    pub fn new(n: usize) -> Self {
-        Self { n, current: KGram::new(n), kgrams: vec![] }
-    }
+/// The labels of a basic block and its successors for the path k-grams.
+struct Block {
+    name: Name,
+    labels: Vec<String>,
+    successors: Vec<Name>,
 }
 
-impl Extractor for KGramExtractor {
-    fn btype(&self) -> crate::birthmarks::BirthmarkType {
-        use crate::birthmarks::BirthmarkType::*;
-        match self.n {
+impl KGramExtractor {
+    /// builds the extractor of the opcode k-grams with the fixed k, from [`BirthmarkType::UniGram`] to [`BirthmarkType::OctaGram`].
+    /// The other k is treated as [`BirthmarkType::KGram`].
+    pub fn new(n: usize, abstraction: Abstraction) -> Self {
+        use BirthmarkType::*;
+        let btype = match n {
             1 => UniGram,
             2 => BiGram,
             3 => TriGram,
@@ -188,38 +387,142 @@ impl Extractor for KGramExtractor {
             6 => HexaGram,
             7 => HeptaGram,
             8 => OctaGram,
-            _ => panic!("Unsupported k-gram size: {}", self.n),
-        }
+            _ => KGram,
+        };
+        Self::with_stream(btype, n, Stream::Opcodes(Box::new(Labeler::new(abstraction))))
     }
 
-    fn visit(&mut self, _module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+    /// builds the extractor of the opcode k-grams with the arbitrary k ([`BirthmarkType::KGram`]).
+    pub fn arbitrary(n: usize, abstraction: Abstraction) -> Self {
+        Self::with_stream(BirthmarkType::KGram, n, Stream::Opcodes(Box::new(Labeler::new(abstraction))))
     }
 
-    fn visit_func(&mut self, _func: &llvm_ir::Function) {
+    /// builds the extractor of the k-grams of the callee names ([`BirthmarkType::CallKGram`]).
+    pub fn calls(n: usize, form: NameForm) -> Self {
+        Self::with_stream(BirthmarkType::CallKGram, n, Stream::Calls(form))
     }
 
-    fn visit_bb(&mut self, _bb: &llvm_ir::basicblock::BasicBlock) {
+    fn with_stream(btype: BirthmarkType, n: usize, stream: Stream) -> Self {
+        Self { btype, stream, boundary: Boundary::default(), block_windows: false, n, current: KGram::new(n), kgrams: vec![], blocks: vec![] }
     }
 
-    fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
-        self.current.push(instruction_to_str(instr));
+    pub fn with_boundary(self, boundary: Boundary) -> Self {
+        Self { boundary, ..self }
+    }
+
+    pub fn with_mode(self, mode: &Mode) -> Self {
+        Self { block_windows: *mode == Mode::BasicBlock, ..self }
+    }
+
+    fn push(&mut self, label: Option<String>) -> Option<Element> {
+        let label = label?;
+        if self.boundary == Boundary::Path {
+            if let Some(block) = self.blocks.last_mut() {
+                block.labels.push(label);
+            }
+            return None;
+        }
+        self.current.push(label);
         if self.current.is_valid() {
             self.kgrams.push(self.current.clone());
-            Ok(Some(self.current.to_elem()))
+            Some(self.current.to_elem())
         } else {
-            Ok(None)
+            None
+        }
+    }
+}
+
+impl Extractor for KGramExtractor {
+    fn btype(&self) -> BirthmarkType {
+        self.btype.clone()
+    }
+
+    fn k(&self) -> Option<usize> {
+        match self.btype {
+            BirthmarkType::KGram | BirthmarkType::CallKGram => Some(self.n),
+            _ => None,
+        }
+    }
+
+    fn names(&self) -> Option<NameForm> {
+        match self.stream {
+            Stream::Calls(form) => Some(form),
+            Stream::Opcodes(_) => None,
+        }
+    }
+
+    fn abstraction(&self) -> Option<Abstraction> {
+        match &self.stream {
+            Stream::Opcodes(labeler) => Some(labeler.abstraction),
+            Stream::Calls(_) => None,
+        }
+    }
+
+    fn boundary(&self) -> Option<Boundary> {
+        Some(self.boundary)
+    }
+
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        if let Stream::Opcodes(labeler) = &mut self.stream {
+            labeler.visit(module);
+        }
+    }
+
+    fn visit_func(&mut self, _func: &llvm_ir::Function) {
+        if self.boundary != Boundary::Module {
+            self.current = KGram::new(self.n);
         }
+        self.blocks.clear();
+    }
+
+    fn visit_bb(&mut self, bb: &llvm_ir::basicblock::BasicBlock) {
+        match self.boundary {
+            Boundary::BasicBlock => self.current = KGram::new(self.n),
+            Boundary::Path => self.blocks.push(Block { name: bb.name.clone(), labels: vec![], successors: successors(&bb.term) }),
+            _ => (),
+        }
+    }
+
+    fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
+        let label = match &self.stream {
+            Stream::Opcodes(labeler) => Some(labeler.instruction(instr)),
+            Stream::Calls(form) => match instr {
+                Instruction::Call(call) => extract_called_name(call, *form),
+                _ => None,
+            },
+        };
+        Ok(self.push(label))
     }
 
     fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
-        self.current.push(terminator_to_str(term));
-        if self.current.is_valid() {
-            self.kgrams.push(self.current.clone());
+        let label = match &self.stream {
+            Stream::Opcodes(labeler) => Some(labeler.terminator(term)),
+            Stream::Calls(form) => extract_called_name_from_term(term, *form),
+        };
+        self.push(label);
+        if self.boundary == Boundary::Path {
+            if !self.block_windows {
+                return Ok(vec![]);
+            }
+            let mut window = KGram::new(self.n);
+            let mut kgrams = vec![];
+            for label in self.blocks.last().iter().flat_map(|b| b.labels.iter()) {
+                window.push(label.clone());
+                if window.is_valid() {
+                    kgrams.push(window.to_elem());
+                }
+            }
+            return Ok(kgrams);
         }
         Ok(self.kgrams.clone().into_iter().map(|k| k.to_elem()).collect())
     }
 
     fn visit_func_end(&mut self, _func: &llvm_ir::Function) -> Result<Vec<Element>> {
+        if self.boundary == Boundary::Path {
+            let kgrams = path_kgrams(&self.blocks, self.n);
+            self.kgrams.extend(kgrams);
+            self.blocks.clear();
+        }
         Ok(self.kgrams.clone().into_iter().map(|k| k.to_elem()).collect())
     }
 
@@ -234,9 +537,162 @@ impl Extractor for KGramExtractor {
     fn clear(&mut self) {
         self.current = KGram::new(self.n);
         self.kgrams.clear();
+        self.blocks.clear();
+    }
+}
+
+/// extracts the skip-grams of the instruction labels, k labels in order with up to s skipped labels among them,
+/// which are resilient to the junk instructions inserted by the obfuscators.
+/// The elements are the frequencies of the skip-grams in the compact form,
+/// the labels joined by `|`, such as `[2, "Load|ICmp|CondBr"]`.
+pub(super) struct SkipGramExtractor {
+    labeler: Labeler,
+    boundary: Boundary,
+    k: usize,
+    skip: usize,
+    window: KGram,
+    freq: BTreeMap<String, usize>,
+}
+
+impl SkipGramExtractor {
+    pub fn new(k: usize, skip: usize, abstraction: Abstraction, boundary: Boundary) -> Self {
+        Self { labeler: Labeler::new(abstraction), boundary, k, skip, window: KGram::new(k + skip), freq: BTreeMap::new() }
+    }
+
+    /// counts the skip-grams ending with the label, so that each of them is counted once.
+    fn push(&mut self, label: String) {
+        self.window.push(label);
+        for gram in self.window.skip_grams(self.k) {
+            *self.freq.entry(gram.join("|")).or_insert(0) += 1;
+        }
+    }
+
+    fn elements(&self) -> Vec<Element> {
+        self.freq.iter().map(|(gram, count)| Element::Freq(*count, gram.clone())).collect()
     }
 }
 
+impl Extractor for SkipGramExtractor {
+    fn btype(&self) -> BirthmarkType {
+        BirthmarkType::SkipGram
+    }
+
+    fn k(&self) -> Option<usize> {
+        Some(self.k)
+    }
+
+    fn skip(&self) -> Option<usize> {
+        Some(self.skip)
+    }
+
+    fn abstraction(&self) -> Option<Abstraction> {
+        Some(self.labeler.abstraction)
+    }
+
+    fn boundary(&self) -> Option<Boundary> {
+        match self.boundary {
+            Boundary::Path => Some(Boundary::Function),
+            boundary => Some(boundary),
+        }
+    }
+
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        self.labeler.visit(module);
+    }
+
+    fn visit_func(&mut self, _func: &llvm_ir::Function) {
+        if self.boundary != Boundary::Module {
+            self.window = KGram::new(self.k + self.skip);
+        }
+    }
+
+    fn visit_bb(&mut self, _bb: &llvm_ir::basicblock::BasicBlock) {
+        if self.boundary == Boundary::BasicBlock {
+            self.window = KGram::new(self.k + self.skip);
+        }
+    }
+
+    fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
+        let label = self.labeler.instruction(instr);
+        self.push(label);
+        Ok(None)
+    }
+
+    fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        let label = self.labeler.terminator(term);
+        self.push(label);
+        Ok(self.elements())
+    }
+
+    fn visit_func_end(&mut self, _func: &llvm_ir::Function) -> Result<Vec<Element>> {
+        Ok(self.elements())
+    }
+
+    fn visit_end(&mut self, _module: &llvm_ir::Module) -> Result<Vec<Element>> {
+        Ok(self.elements())
+    }
+
+    fn finish(&self) -> Result<Vec<crate::birthmarks::Birthmark>> {
+        Ok(vec![])
+    }
+
+    fn clear(&mut self) {
+        self.window = KGram::new(self.k + self.skip);
+        self.freq.clear();
+    }
+}
+
+/// The upper limit of the distinct paths from a position, since the paths grow exponentially with k.
+const MAX_PATHS: usize = 1024;
+
+/// The upper limit of the states visited by the walk from a position, since the branches multiply the paths
+/// even when no k-gram is completed, e.g., the chains of the diamonds without any calls.
+const MAX_STEPS: usize = 1 << 16;
+
+/// returns the distinct k-grams of the paths from every position of every block, following the successors.
+fn path_kgrams(blocks: &[Block], k: usize) -> Vec<KGram> {
+    let index = blocks.iter().enumerate()
+        .map(|(i, b)| (&b.name, i))
+        .collect::<HashMap<_, _>>();
+    let mut kgrams = vec![];
+    for (b, block) in blocks.iter().enumerate() {
+        for i in 0..block.labels.len() {
+            let paths = walk(blocks, &index, (b, i), k);
+            kgrams.extend(paths.into_iter().map(|elements| KGram { value: k, elements }));
+        }
+    }
+    kgrams
+}
+
+/// extends the prefix with the labels from the position, and then with the labels of the successors.
+/// The walk visits each pair of a block and a prefix at most once, hence the blocks without labels
+/// neither loop forever nor multiply the paths.
+fn walk(blocks: &[Block], index: &HashMap<&Name, usize>, start: (usize, usize), k: usize) -> BTreeSet<Vec<String>> {
+    let mut paths = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![(start, vec![])];
+    let mut steps = 0;
+    while let Some(((b, i), mut prefix)) = stack.pop() {
+        steps += 1;
+        if paths.len() >= MAX_PATHS || steps > MAX_STEPS {
+            break;
+        }
+        let labels = &blocks[b].labels[i.min(blocks[b].labels.len())..];
+        let take = (k - prefix.len()).min(labels.len());
+        prefix.extend(labels[..take].iter().cloned());
+        if prefix.len() == k {
+            paths.insert(prefix);
+            continue;
+        }
+        for successor in blocks[b].successors.iter().rev() {
+            if let Some(&next) = index.get(successor) && visited.insert((next, prefix.clone())) {
+                stack.push(((next, 0), prefix.clone()));
+            }
+        }
+    }
+    paths
+}
+
 #[derive(Clone)]
 struct KGram {
     value: usize,
@@ -259,9 +715,34 @@ impl KGram {
     pub fn to_elem(&self) -> Element {
         Element::Kgram(self.elements.clone())
     }
+    /// returns the subsequences of length k ending with the last element of the window,
+    /// whose skipped elements are bounded by the window size.
+    pub fn skip_grams(&self, k: usize) -> Vec<Vec<String>> {
+        match self.elements.split_last() {
+            Some((last, rest)) if k > 0 && rest.len() >= k - 1 => {
+                let mut grams = vec![];
+                choose(rest, k - 1, &mut vec![], &mut grams);
+                grams.into_iter().map(|mut g| { g.push(last.clone()); g }).collect()
+            },
+            _ => vec![],
+        }
+    }
+}
+
+/// collects the combinations of r items in order.
+fn choose(items: &[String], r: usize, current: &mut Vec<String>, result: &mut Vec<Vec<String>>) {
+    if r == 0 {
+        result.push(current.clone());
+        return;
+    }
+    for i in 0..=(items.len() - r) {
+        current.push(items[i].clone());
+        choose(&items[i + 1..], r - 1, current, result);
+        current.pop();
+    }
 }
 
-fn terminator_to_str(term: &llvm_ir::Terminator) -> String {
+pub(super) fn terminator_to_str(term: &llvm_ir::Terminator) -> String {
     use llvm_ir::Terminator::*;
     match term {
         Ret(_ret) => "Ret",
@@ -279,7 +760,7 @@ fn terminator_to_str(term: &llvm_ir::Terminator) -> String {
     }.to_string()
 }
 
-fn instruction_to_str(inst: &llvm_ir::Instruction) -> String {
+pub(super) fn instruction_to_str(inst: &llvm_ir::Instruction) -> String {
     use llvm_ir::Instruction::*;
     match inst {
         Add(_item) => "Add",
@@ -342,7 +823,7 @@ fn instruction_to_str(inst: &llvm_ir::Instruction) -> String {
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
-    use crate::{birthmarks::BirthmarkType, extractors::{extract, Mode}};
+    use crate::{birthmarks::BirthmarkType, extractors::{extract, extract_with, Mode, Options}};
 
     use super::*;
 
@@ -379,4 +860,142 @@ mod tests {
         assert!(res.contains(&Element::Str("Ret".into())));
         assert_eq!(res.len(), 2);
     }
+
+    #[test]
+    fn test_abstraction() {
+        let ir = r#"
+        declare void @llvm.donothing()
+
+        define i64 @f(i8 %a, ptr %p) {
+        entry:
+            %c = icmp sgt i8 %a, 0
+            %w = zext i8 %a to i64
+            %v = load volatile i64, ptr %p, align 8
+            call void @llvm.donothing()
+            ret i64 %w
+        }
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let labels = |abstraction| {
+            let opts = Options { abstraction, ..Options::default() };
+            extract_with(&module, "<memory>", &BirthmarkType::OpSeq, &Mode::File, &opts).unwrap()
+                .remove(0).elements
+        };
+        assert_eq!(labels(Abstraction::Typed), ["ICmp int", "ZExt int", "Load int", "Call void", "Ret int"]
+            .map(|s| Element::Str(s.into())));
+        assert_eq!(labels(Abstraction::Operand), [
+            "ICmp int sgt", "ZExt int i8 i64", "Load int align8 volatile", "Call void intrinsic", "Ret int",
+        ].map(|s| Element::Str(s.into())));
+
+        let birthmarks = extract(&module, "<memory>", &BirthmarkType::OpSeq, &Mode::File).unwrap();
+        assert_eq!(birthmarks[0].info.abstraction, Some(Abstraction::Opcode));
+        let opts = Options { abstraction: Abstraction::Typed, ..Options::default() };
+        let typed = extract_with(&module, "<memory>", &BirthmarkType::OpSeq, &Mode::File, &opts).unwrap();
+        assert!(!typed[0].info.is_same_type(&birthmarks[0].info));
+    }
+
+    #[test]
+    fn test_arbitrary_k() {
+        let ir = r#"
+        declare void @g()
+
+        define void @f() {
+        entry:
+            call void @g()
+            call void @g()
+            call void @g()
+            ret void
+        }
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let opts = Options { kgram_size: 4, ..Options::default() };
+        let birthmarks = extract_with(&module, "<memory>", &BirthmarkType::KGram, &Mode::File, &opts).unwrap();
+        assert_eq!(birthmarks[0].info.k, Some(4));
+        assert_eq!(birthmarks[0].elements, vec![Element::Kgram(vec!["Call".into(), "Call".into(), "Call".into(), "Ret".into()])]);
+
+        let opts = Options { kgram_size: 2, ..Options::default() };
+        let birthmarks = extract_with(&module, "<memory>", &BirthmarkType::CallKGram, &Mode::File, &opts).unwrap();
+        assert_eq!(birthmarks[0].info.k, Some(2));
+        assert_eq!(birthmarks[0].elements.len(), 2);
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let ir = r#"
+        define void @f(i1 %c) {
+        entry:
+            br i1 %c, label %a, label %b
+        a:
+            %x = add i32 1, 2
+            br label %exit
+        b:
+            %y = sub i32 1, 2
+            br label %exit
+        exit:
+            ret void
+        }
+
+        define void @g() {
+        entry:
+            ret void
+        }
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let bigrams = |kgram_boundary| {
+            let opts = Options { kgram_boundary, ..Options::default() };
+            extract_with(&module, "<memory>", &BirthmarkType::BiGram, &Mode::File, &opts).unwrap()
+                .remove(0).elements
+        };
+        let kgram = |a: &str, b: &str| Element::Kgram(vec![a.into(), b.into()]);
+        assert!(bigrams(Boundary::Module).contains(&kgram("Ret", "Ret")));
+        assert_eq!(bigrams(Boundary::Function), vec![
+            kgram("CondBr", "Add"), kgram("Add", "Br"), kgram("Br", "Sub"), kgram("Sub", "Br"), kgram("Br", "Ret"),
+        ]);
+        assert_eq!(bigrams(Boundary::BasicBlock), vec![kgram("Add", "Br"), kgram("Sub", "Br")]);
+        let birthmarks = extract(&module, "<memory>", &BirthmarkType::BiGram, &Mode::File).unwrap();
+        assert_eq!(birthmarks[0].info.boundary, Some(Boundary::Function));
+        let opts = Options { kgram_boundary: Boundary::Path, ..Options::default() };
+        let paths = extract_with(&module, "<memory>", &BirthmarkType::BiGram, &Mode::File, &opts).unwrap();
+        assert!(!paths[0].info.is_same_type(&birthmarks[0].info));
+        assert_eq!(bigrams(Boundary::Path), vec![
+            kgram("CondBr", "Add"), kgram("CondBr", "Sub"), kgram("Add", "Br"), kgram("Br", "Ret"), kgram("Sub", "Br"), kgram("Br", "Ret"),
+        ]);
+    }
+
+    #[test]
+    fn test_path_diamonds() {
+        let mut ir = String::from("declare void @g()\ndeclare void @h()\n\ndefine void @f(i1 %c) {\nentry:\n    call void @g()\n    br label %d0\n");
+        for i in 0..40 {
+            ir.push_str(&format!("d{i}:\n    br i1 %c, label %l{i}, label %r{i}\nl{i}:\n    br label %d{n}\nr{i}:\n    br label %d{n}\n", n = i + 1));
+        }
+        ir.push_str("d40:\n    call void @h()\n    ret void\n}\n");
+        let module = llvm_ir::Module::from_ir_str(&ir).unwrap();
+        let opts = Options { kgram_size: 2, kgram_boundary: Boundary::Path, ..Options::default() };
+        let birthmarks = extract_with(&module, "<memory>", &BirthmarkType::CallKGram, &Mode::File, &opts).unwrap();
+        assert_eq!(birthmarks[0].elements, vec![Element::Kgram(vec!["g".into(), "h".into()])]);
+    }
+
+    #[test]
+    fn test_skip_grams() {
+        let mut window = KGram::new(4);
+        ["A", "X", "B", "C"].iter().for_each(|e| window.push(e.to_string()));
+        let grams = window.skip_grams(2);
+        assert_eq!(grams, vec![vec!["A", "C"], vec!["X", "C"], vec!["B", "C"]]);
+
+        let ir = r#"
+        define i32 @f(i32 %a) {
+        entry:
+            %x = add i32 %a, 1
+            %junk = xor i32 %a, 0
+            %y = sub i32 %x, 1
+            ret i32 %y
+        }
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let opts = Options { kgram_size: 2, skip_size: 1, ..Options::default() };
+        let birthmarks = extract_with(&module, "<memory>", &BirthmarkType::SkipGram, &Mode::File, &opts).unwrap();
+        assert_eq!((birthmarks[0].info.k, birthmarks[0].info.skip), (Some(2), Some(1)));
+        assert!(birthmarks[0].elements.contains(&Element::Freq(1, "Add|Sub".into())));
+        assert_eq!(birthmarks[0].elements.len(), 5);
+    }
 }
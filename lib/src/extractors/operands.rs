@@ -1,19 +1,268 @@
-use crate::birthmarks::Element;
+use std::collections::HashMap;
+
+use llvm_ir::constant::Float;
+use llvm_ir::module::GlobalVariable;
+use llvm_ir::{Constant, ConstantRef, Instruction, Name, Operand, Terminator, Type};
+
+use crate::birthmarks::{BirthmarkType, Element};
+use crate::extractors::Extractor;
 use crate::Result;
 
-#[allow(dead_code)]
-pub(super) fn extract(module: &llvm_ir::Module) -> Result<Vec<Element>> {
-    let mut operands = Vec::new();
-    for func in &module.functions {
-        for bb in &func.basic_blocks {
-            for instr in &bb.instrs {
-                let operand = format!("{:?}", instr);
-                let operand = operand.split_whitespace().next().unwrap_or("");
-                if !operand.is_empty() {
-                    operands.push(Element::Str(operand.to_string()));
-                }
+/// extracts the constant values (CVFV, Tamada et al. 2004) in the order of appearance,
+/// the integer, float, and string constants of the operands and the global initializers,
+/// annotated with their types, such as `i32 42`, `double 0.5`, and `str "hello"`.
+/// The global initializers precede the operands in the file mode,
+/// and the string literals (the unnamed constant globals) appear where they are used.
+pub(super) struct ConstantExtractor {
+    strings: HashMap<Name, String>,
+    globals: Vec<Element>,
+    values: Vec<Element>,
+}
+
+impl ConstantExtractor {
+    pub fn new() -> Self {
+        Self { strings: HashMap::new(), globals: vec![], values: vec![] }
+    }
+
+    fn push_operand(&mut self, operand: &Operand) -> Option<Element> {
+        if let Operand::ConstantOperand(c) = operand {
+            let mut values = vec![];
+            constant_values(c, &self.strings, &mut values);
+            let last = values.last().cloned();
+            self.values.extend(values);
+            last
+        } else {
+            None
+        }
+    }
+}
+
+impl Extractor for ConstantExtractor {
+    fn btype(&self) -> BirthmarkType {
+        BirthmarkType::Cvfv
+    }
+
+    fn visit(&mut self, module: &llvm_ir::Module, _path: &std::path::PathBuf) {
+        self.strings = module.global_vars.iter()
+            .filter_map(|g| g.initializer.as_ref()
+                .and_then(decode_string)
+                .map(|s| (g.name.clone(), s)))
+            .collect();
+        self.globals.clear();
+        for global in module.global_vars.iter().filter(|g| !is_string_literal(g)) {
+            if let Some(initializer) = &global.initializer {
+                constant_values(initializer, &self.strings, &mut self.globals);
             }
         }
     }
-    Ok(operands)
-}
\ No newline at end of file
+
+    fn visit_func(&mut self, _func: &llvm_ir::Function) {
+    }
+
+    fn visit_bb(&mut self, _bb: &llvm_ir::basicblock::BasicBlock) {
+    }
+
+    fn visit_inst(&mut self, instr: &llvm_ir::Instruction) -> Result<Option<Element>> {
+        let mut last = None;
+        for operand in instruction_operands(instr) {
+            last = self.push_operand(operand).or(last);
+        }
+        Ok(last)
+    }
+
+    fn visit_bb_end(&mut self, term: &llvm_ir::terminator::Terminator) -> Result<Vec<Element>> {
+        for operand in terminator_operands(term) {
+            self.push_operand(operand);
+        }
+        if let Terminator::Switch(switch) = term {
+            for (value, _) in &switch.dests {
+                constant_values(value, &self.strings, &mut self.values);
+            }
+        }
+        Ok(self.values.clone())
+    }
+
+    fn visit_func_end(&mut self, _func: &llvm_ir::Function) -> Result<Vec<Element>> {
+        Ok(self.values.clone())
+    }
+
+    fn visit_end(&mut self, _module: &llvm_ir::Module) -> Result<Vec<Element>> {
+        Ok(self.globals.iter().chain(self.values.iter()).cloned().collect())
+    }
+
+    fn finish(&self) -> Result<Vec<crate::birthmarks::Birthmark>> {
+        Ok(vec![])
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+/// collects the annotated values of the constant, through the aggregates and the constant expressions.
+/// The references to the globals holding strings are resolved into the strings.
+fn constant_values(c: &ConstantRef, strings: &HashMap<Name, String>, values: &mut Vec<Element>) {
+    match c.as_ref() {
+        Constant::Int { bits, value } => values.push(Element::Str(format!("i{} {}", bits, signed(*bits, *value)))),
+        Constant::Float(Float::Single(f)) => values.push(Element::Str(format!("float {}", f))),
+        Constant::Float(Float::Double(f)) => values.push(Element::Str(format!("double {}", f))),
+        Constant::GlobalReference { name, .. } => if let Some(s) = strings.get(name) {
+            values.push(Element::Str(format!("str {:?}", s)));
+        },
+        Constant::Array { elements, .. } => match decode_string(c) {
+            Some(s) => values.push(Element::Str(format!("str {:?}", s))),
+            None => elements.iter().for_each(|e| constant_values(e, strings, values)),
+        },
+        Constant::Struct { values: elements, .. } | Constant::Vector(elements) =>
+            elements.iter().for_each(|e| constant_values(e, strings, values)),
+        Constant::GetElementPtr(gep) => constant_values(&gep.address, strings, values),
+        Constant::BitCast(cast) => constant_values(&cast.operand, strings, values),
+        Constant::AddrSpaceCast(cast) => constant_values(&cast.operand, strings, values),
+        _ => (),
+    }
+}
+
+/// renders the integer of the given width as a signed value, except the booleans.
+/// The integers wider than 64 bits hold only the lower 64 bits, which are rendered in hex as `trunc64:0x...`.
+pub(super) fn signed(bits: u32, value: u64) -> String {
+    match bits {
+        0..=1 => value.to_string(),
+        2..=64 => {
+            let shift = 64 - bits;
+            (((value << shift) as i64) >> shift).to_string()
+        },
+        _ => format!("trunc64:{:#x}", value),
+    }
+}
+
+/// decodes the constant `i8` array into the string, without the trailing NUL.
+pub(super) fn decode_string(c: &ConstantRef) -> Option<String> {
+    match c.as_ref() {
+        Constant::Array { element_type, elements } if matches!(element_type.as_ref(), Type::IntegerType { bits: 8 }) => {
+            let mut bytes = elements.iter()
+                .map(|e| match e.as_ref() {
+                    Constant::Int { value, .. } => Some(*value as u8),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            if bytes.last() == Some(&0) {
+                bytes.pop();
+            }
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        },
+        _ => None,
+    }
+}
+
+/// the string literals are the unnamed_addr constant globals holding strings, such as `@.str`.
+fn is_string_literal(global: &GlobalVariable) -> bool {
+    global.is_constant && global.unnamed_addr.is_some()
+        && global.initializer.as_ref().is_some_and(|i| decode_string(i).is_some())
+}
+
+/// returns the value operands of the instruction.
+/// The callees, the allocation sizes, and the indices of `getelementptr` are excluded.
+fn instruction_operands(instr: &Instruction) -> Vec<&Operand> {
+    use Instruction::*;
+    match instr {
+        Add(i) => vec![&i.operand0, &i.operand1],
+        Sub(i) => vec![&i.operand0, &i.operand1],
+        Mul(i) => vec![&i.operand0, &i.operand1],
+        UDiv(i) => vec![&i.operand0, &i.operand1],
+        SDiv(i) => vec![&i.operand0, &i.operand1],
+        URem(i) => vec![&i.operand0, &i.operand1],
+        SRem(i) => vec![&i.operand0, &i.operand1],
+        FAdd(i) => vec![&i.operand0, &i.operand1],
+        FSub(i) => vec![&i.operand0, &i.operand1],
+        FMul(i) => vec![&i.operand0, &i.operand1],
+        FDiv(i) => vec![&i.operand0, &i.operand1],
+        FRem(i) => vec![&i.operand0, &i.operand1],
+        Shl(i) => vec![&i.operand0, &i.operand1],
+        LShr(i) => vec![&i.operand0, &i.operand1],
+        AShr(i) => vec![&i.operand0, &i.operand1],
+        And(i) => vec![&i.operand0, &i.operand1],
+        Or(i) => vec![&i.operand0, &i.operand1],
+        Xor(i) => vec![&i.operand0, &i.operand1],
+        ICmp(i) => vec![&i.operand0, &i.operand1],
+        FCmp(i) => vec![&i.operand0, &i.operand1],
+        FNeg(i) => vec![&i.operand],
+        Freeze(i) => vec![&i.operand],
+        Trunc(i) => vec![&i.operand],
+        ZExt(i) => vec![&i.operand],
+        SExt(i) => vec![&i.operand],
+        FPToUI(i) => vec![&i.operand],
+        FPToSI(i) => vec![&i.operand],
+        UIToFP(i) => vec![&i.operand],
+        SIToFP(i) => vec![&i.operand],
+        FPTrunc(i) => vec![&i.operand],
+        FPExt(i) => vec![&i.operand],
+        PtrToInt(i) => vec![&i.operand],
+        IntToPtr(i) => vec![&i.operand],
+        BitCast(i) => vec![&i.operand],
+        AddrSpaceCast(i) => vec![&i.operand],
+        Load(i) => vec![&i.address],
+        Store(i) => vec![&i.value, &i.address],
+        GetElementPtr(i) => vec![&i.address],
+        Phi(i) => i.incoming_values.iter().map(|(v, _)| v).collect(),
+        Select(i) => vec![&i.condition, &i.true_value, &i.false_value],
+        Call(i) => i.arguments.iter().map(|(a, _)| a).collect(),
+        ExtractElement(i) => vec![&i.vector, &i.index],
+        InsertElement(i) => vec![&i.vector, &i.element, &i.index],
+        ShuffleVector(i) => vec![&i.operand0, &i.operand1],
+        ExtractValue(i) => vec![&i.aggregate],
+        InsertValue(i) => vec![&i.aggregate, &i.element],
+        AtomicRMW(i) => vec![&i.address, &i.value],
+        CmpXchg(i) => vec![&i.address, &i.expected, &i.replacement],
+        CatchPad(i) => i.args.iter().collect(),
+        CleanupPad(i) => i.args.iter().collect(),
+        Alloca(_) | LandingPad(_) | Fence(_) | VAArg(_) => vec![],
+    }
+}
+
+fn terminator_operands(term: &Terminator) -> Vec<&Operand> {
+    match term {
+        Terminator::Ret(ret) => ret.return_operand.iter().collect(),
+        Terminator::CondBr(br) => vec![&br.condition],
+        Terminator::Switch(switch) => vec![&switch.operand],
+        Terminator::Invoke(invoke) => invoke.arguments.iter().map(|(a, _)| a).collect(),
+        Terminator::CallBr(call_br) => call_br.arguments.iter().map(|(a, _)| a).collect(),
+        Terminator::Resume(resume) => vec![&resume.operand],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::extractors::{extract, Mode};
+
+    use super::*;
+
+    #[test]
+    fn test_constants() {
+        let ir = r#"
+        @.str = private unnamed_addr constant [6 x i8] c"hello\00", align 1
+        @table = global [2 x i16] [i16 -1, i16 7], align 2
+
+        declare i32 @puts(ptr)
+
+        define i32 @f(i32 %n) {
+        entry:
+            %r = call i32 @puts(ptr @.str)
+            %m = mul i32 %n, 42
+            %x = sext i32 %n to i64
+            %y = add i64 %x, -1
+            %d = fmul double 5.000000e-01, 2.000000e+00
+            ret i32 %m
+        }
+        "#;
+        let module = llvm_ir::Module::from_ir_str(ir).unwrap();
+        let birthmarks = extract(&module, PathBuf::from("<memory>"), &BirthmarkType::Cvfv, &Mode::File).unwrap();
+        assert_eq!(birthmarks[0].elements, [
+            "i16 -1", "i16 7", "str \"hello\"", "i32 42", "i64 -1", "double 0.5", "double 2",
+        ].map(|s| Element::Str(s.into())));
+        assert_eq!(signed(64, u64::MAX), "-1");
+        assert_eq!(signed(1, 1), "1");
+        assert_eq!(signed(128, 3), "trunc64:0x3");
+    }
+}
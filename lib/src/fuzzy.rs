@@ -0,0 +1,246 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::birthmarks::{Birthmark, Element, Info};
+use crate::{OinkieError, Result};
+
+const ROLLING_WINDOW: usize = 7;
+const SPAMSUM_LENGTH: usize = 64;
+const MIN_BLOCK_SIZE: u32 = 3;
+const HASH_INIT: u32 = 0x28021967;
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The compact fingerprint of a birthmark, which is stored instead of the elements.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Digest {
+    pub info: Info,
+    pub hash: Ctph,
+}
+
+impl Digest {
+    pub fn new(birthmark: &Birthmark) -> Self {
+        Self { info: birthmark.info.clone(), hash: Ctph::new(&birthmark.elements) }
+    }
+
+    pub fn similarity(&self, other: &Digest) -> f64 {
+        self.hash.similarity(&other.hash)
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Digest>> {
+    match std::fs::File::open(path.as_ref()) {
+        Ok(file) => serde_json::from_reader(file).map_err(OinkieError::Json),
+        Err(e) => Err(OinkieError::Io(e)),
+    }
+}
+
+/// The context triggered piecewise hash (the ssdeep style fuzzy hash) over the element stream,
+/// serialized as `block_size:first:second`.
+/// The element stream should be ordered, such as [`OpSeq`](crate::birthmarks::BirthmarkType::OpSeq)
+/// and [`Sfc`](crate::birthmarks::BirthmarkType::Sfc).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Ctph {
+    pub block_size: u32,
+    pub first: String,
+    pub second: String,
+}
+
+impl Ctph {
+    pub fn new(elements: &[Element]) -> Self {
+        let hashes = elements.iter()
+            .map(|e| e.key().stable_hash() as u32)
+            .collect::<Vec<_>>();
+        let mut block_size = MIN_BLOCK_SIZE;
+        while (block_size as usize) * SPAMSUM_LENGTH < hashes.len() {
+            block_size *= 2;
+        }
+        loop {
+            let (first, second) = pieces(&hashes, block_size);
+            if block_size > MIN_BLOCK_SIZE && first.len() < SPAMSUM_LENGTH / 2 {
+                block_size /= 2;
+            } else {
+                return Self { block_size, first, second };
+            }
+        }
+    }
+
+    /// returns the similarity in `[0, 1]` of two hashes.
+    /// The hashes are comparable only if the block sizes are the same or one is twice the other.
+    pub fn similarity(&self, other: &Ctph) -> f64 {
+        if self.block_size == other.block_size {
+            score(&self.first, &other.first).max(score(&self.second, &other.second))
+        } else if self.block_size == other.block_size * 2 {
+            score(&self.first, &other.second)
+        } else if other.block_size == self.block_size * 2 {
+            score(&self.second, &other.first)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Display for Ctph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.block_size, self.first, self.second)
+    }
+}
+
+impl FromStr for Ctph {
+    type Err = OinkieError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let items = s.splitn(3, ':').collect::<Vec<_>>();
+        match items.as_slice() {
+            [bs, first, second] => match bs.parse::<u32>() {
+                Ok(block_size) => Ok(Self { block_size, first: first.to_string(), second: second.to_string() }),
+                Err(e) => Err(OinkieError::Format(format!("{}: invalid block size ({})", s, e))),
+            },
+            _ => Err(OinkieError::Format(format!("{}: invalid fuzzy hash", s))),
+        }
+    }
+}
+
+impl TryFrom<String> for Ctph {
+    type Error = OinkieError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Ctph> for String {
+    fn from(value: Ctph) -> Self {
+        value.to_string()
+    }
+}
+
+fn pieces(hashes: &[u32], block_size: u32) -> (String, String) {
+    let mut roll = Roll::default();
+    let (mut h1, mut h2) = (HASH_INIT, HASH_INIT);
+    let (mut first, mut second) = (String::new(), String::new());
+    for &c in hashes {
+        h1 = fnv(h1, c);
+        h2 = fnv(h2, c);
+        let r = roll.update(c);
+        if r % block_size == block_size - 1 && first.len() < SPAMSUM_LENGTH - 1 {
+            first.push(base64(h1));
+            h1 = HASH_INIT;
+        }
+        if r % (block_size * 2) == block_size * 2 - 1 && second.len() < SPAMSUM_LENGTH / 2 - 1 {
+            second.push(base64(h2));
+            h2 = HASH_INIT;
+        }
+    }
+    if h1 != HASH_INIT {
+        first.push(base64(h1));
+    }
+    if h2 != HASH_INIT {
+        second.push(base64(h2));
+    }
+    (first, second)
+}
+
+fn fnv(h: u32, c: u32) -> u32 {
+    c.to_le_bytes().iter()
+        .fold(h, |h, b| (h ^ *b as u32).wrapping_mul(0x01000193))
+}
+
+fn base64(h: u32) -> char {
+    BASE64[(h % 64) as usize] as char
+}
+
+/// The rolling hash of the last [`ROLLING_WINDOW`] elements, which decides the boundaries of the pieces.
+#[derive(Default)]
+struct Roll {
+    window: [u32; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl Roll {
+    fn update(&mut self, c: u32) -> u32 {
+        let index = self.n % ROLLING_WINDOW;
+        self.h2 = self.h2.wrapping_sub(self.h1).wrapping_add((ROLLING_WINDOW as u32).wrapping_mul(c));
+        self.h1 = self.h1.wrapping_add(c).wrapping_sub(self.window[index]);
+        self.window[index] = c;
+        self.n += 1;
+        self.h3 = (self.h3 << 5) ^ c;
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+/// returns `1 - (normalized edit distance)` of two pieces,
+/// after the runs of more than three identical characters are reduced to three.
+fn score(s1: &str, s2: &str) -> f64 {
+    let s1 = eliminate_sequences(s1);
+    let s2 = eliminate_sequences(s2);
+    match (s1.len(), s2.len()) {
+        (0, 0) => 1.0,
+        (0, _) | (_, 0) => 0.0,
+        (l1, l2) => 1.0 - edit_distance(&s1, &s2) as f64 / l1.max(l2) as f64,
+    }
+}
+
+fn eliminate_sequences(s: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = vec![];
+    for &c in s.as_bytes() {
+        let len = result.len();
+        if len < 3 || result[len - 3..].iter().any(|&p| p != c) {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(seed: u64, len: usize) -> Vec<Element> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            Element::Str(format!("op{}", (state >> 33) % 20))
+        }).collect()
+    }
+
+    #[test]
+    fn test_similar_streams() {
+        let a = elements(1, 2000);
+        let mut b = a.clone();
+        b.splice(1000..1010, elements(2, 5));
+        let c = elements(3, 2000);
+        let (ha, hb, hc) = (Ctph::new(&a), Ctph::new(&b), Ctph::new(&c));
+        assert_eq!(ha.similarity(&ha), 1.0);
+        assert!(ha.similarity(&hb) > 0.8);
+        assert!(ha.similarity(&hb) > ha.similarity(&hc));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let h = Ctph::new(&elements(1, 500));
+        let s = h.to_string();
+        assert_eq!(s.parse::<Ctph>().unwrap(), h);
+        assert_eq!(serde_json::to_string(&h).unwrap(), format!("\"{}\"", s));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::birthmarks::Fnv;
+
+/// The number of iterations of the Weisfeiler-Lehman relabeling.
+pub(crate) const WL_ITERATIONS: usize = 3;
+
+/// The directed graph with the labeled nodes, the first node is the entry.
+pub(crate) struct Graph {
+    labels: Vec<u64>,
+    succs: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    /// builds the graph from the edges of named nodes, and all nodes have the same label,
+    /// so that the graphs are compared by their structures, regardless of the node names.
+    pub(crate) fn from_edges<'a>(edges: impl Iterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut index = HashMap::<&str, usize>::new();
+        let mut succs = Vec::<Vec<usize>>::new();
+        let mut node = |name: &'a str, succs: &mut Vec<Vec<usize>>| {
+            let next = index.len();
+            *index.entry(name).or_insert_with(|| {
+                succs.push(vec![]);
+                next
+            })
+        };
+        for (from, to) in edges {
+            let from = node(from, &mut succs);
+            let to = node(to, &mut succs);
+            succs[from].push(to);
+        }
+        Self::new(vec![0; succs.len()], succs)
+    }
+
+    pub(crate) fn new(labels: Vec<u64>, succs: Vec<Vec<usize>>) -> Self {
+        let mut preds = vec![vec![]; labels.len()];
+        for (from, ss) in succs.iter().enumerate() {
+            for to in ss {
+                preds[*to].push(from);
+            }
+        }
+        Self { labels, succs, preds }
+    }
+
+    pub(crate) fn invariants(&self) -> String {
+        let nodes = self.labels.len();
+        let edges = self.succs.iter().map(|s| s.len()).sum::<usize>();
+        let exits = self.succs.iter().filter(|s| s.is_empty()).count();
+        let max_out = self.succs.iter().map(|s| s.len()).max().unwrap_or(0);
+        let max_in = self.preds.iter().map(|p| p.len()).max().unwrap_or(0);
+        format!("nodes={} edges={} exits={} back_edges={} max_out={} max_in={} depth={}",
+            nodes, edges, exits, self.back_edges(), max_out, max_in, self.depth())
+    }
+
+    /// counts the edges to the ancestors in the depth first search tree from the entry (the loops).
+    fn back_edges(&self) -> usize {
+        // 0: unvisited, 1: on the stack, 2: finished
+        let mut state = vec![0u8; self.labels.len()];
+        let mut count = 0;
+        if self.labels.is_empty() {
+            return 0;
+        }
+        let mut stack = vec![(0, 0)];
+        state[0] = 1;
+        while let Some((node, next)) = stack.pop() {
+            if let Some(&succ) = self.succs[node].get(next) {
+                stack.push((node, next + 1));
+                match state[succ] {
+                    0 => {
+                        state[succ] = 1;
+                        stack.push((succ, 0));
+                    },
+                    1 => count += 1,
+                    _ => (),
+                }
+            } else {
+                state[node] = 2;
+            }
+        }
+        count
+    }
+
+    /// returns the largest shortest distance from the entry to the reachable blocks.
+    fn depth(&self) -> usize {
+        if self.labels.is_empty() {
+            return 0;
+        }
+        let mut dist = vec![usize::MAX; self.labels.len()];
+        let mut queue = VecDeque::from([0]);
+        dist[0] = 0;
+        while let Some(node) = queue.pop_front() {
+            for &succ in &self.succs[node] {
+                if dist[succ] == usize::MAX {
+                    dist[succ] = dist[node] + 1;
+                    queue.push_back(succ);
+                }
+            }
+        }
+        dist.into_iter().filter(|d| *d != usize::MAX).max().unwrap_or(0)
+    }
+
+    /// returns the labels of all nodes in all iterations of the Weisfeiler-Lehman relabeling.
+    /// Each label summarizes the subtree of the neighbors (both successors and predecessors) up to the iteration depth.
+    fn wl_iterations(&self) -> Vec<Vec<u64>> {
+        let mut result = vec![self.labels.clone()];
+        for _ in 0..WL_ITERATIONS {
+            let prev = result.last().unwrap();
+            let next = (0..prev.len()).map(|node| {
+                let mut succs = self.succs[node].iter().map(|s| prev[*s]).collect::<Vec<_>>();
+                let mut preds = self.preds[node].iter().map(|p| prev[*p]).collect::<Vec<_>>();
+                succs.sort();
+                preds.sort();
+                let mut neighbors = succs;
+                neighbors.push(0);
+                neighbors.extend(preds);
+                hash(&prev[node].to_le_bytes(), &neighbors)
+            }).collect();
+            result.push(next);
+        }
+        result
+    }
+
+    pub(crate) fn wl_features(&self) -> Vec<u64> {
+        self.wl_iterations().into_iter().flatten().collect()
+    }
+
+    /// returns the hash of the multiset of the final labels, which is the same for the isomorphic graphs.
+    pub(crate) fn wl_hash(&self) -> u64 {
+        let mut labels = self.wl_iterations().pop().unwrap_or_default();
+        labels.sort();
+        hash(&(labels.len() as u64).to_le_bytes(), &labels)
+    }
+}
+
+pub(crate) fn hash(bytes: &[u8], values: &[u64]) -> u64 {
+    let mut h = Fnv::new();
+    h.write(bytes);
+    for v in values {
+        h.write(&v.to_le_bytes());
+    }
+    h.finish()
+}
+
+/// returns the normalized Weisfeiler-Lehman subtree kernel of two graphs,
+/// the cosine similarity of the histograms of the labels in all iterations.
+pub(crate) fn wl_kernel(a: &Graph, b: &Graph) -> f64 {
+    let histogram = |g: &Graph| {
+        let mut map = HashMap::<u64, usize>::new();
+        for label in g.wl_features() {
+            *map.entry(label).or_insert(0) += 1;
+        }
+        map
+    };
+    let (h1, h2) = (histogram(a), histogram(b));
+    let dot = |x: &HashMap<u64, usize>, y: &HashMap<u64, usize>| x.iter()
+        .map(|(k, v)| (*v * y.get(k).unwrap_or(&0)) as f64)
+        .sum::<f64>();
+    match (dot(&h1, &h1), dot(&h2, &h2)) {
+        (n1, n2) if n1 == 0.0 && n2 == 0.0 => 1.0,
+        (n1, n2) if n1 == 0.0 || n2 == 0.0 => 0.0,
+        (n1, n2) => dot(&h1, &h2) / (n1 * n2).sqrt(),
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::birthmarks::{Birthmark, Element, Info, Key};
+use crate::{OinkieError, Result};
+
+/// The on-disk birthmark database, the inverted index over the elements with TF-IDF weights.
+/// The stored birthmarks are searched like the search engines,
+/// without the pairwise comparisons to all of them.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Index {
+    documents: Vec<Document>,
+    #[serde(skip)]
+    postings: HashMap<String, Vec<(usize, usize)>>,
+    #[serde(skip)]
+    groups: Vec<TypeGroup>,
+}
+
+/// A stored birthmark, the frequencies of its terms.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Document {
+    info: Info,
+    terms: HashMap<String, usize>,
+    /// the index of the type group of the document, computed at rebuilding.
+    #[serde(skip)]
+    group: usize,
+    /// the norm of the TF-IDF vector in the type group, computed at rebuilding.
+    #[serde(skip)]
+    norm: f64,
+}
+
+/// The documents of the same birthmark type, which are the targets of the queries of the type.
+/// The document frequencies are counted within the group.
+#[derive(Debug)]
+struct TypeGroup {
+    info: Info,
+    total: usize,
+    df: HashMap<String, usize>,
+}
+
+impl TypeGroup {
+    fn idf(&self, term: &str) -> f64 {
+        let df = self.df.get(term).copied().unwrap_or(0);
+        ((1 + self.total) as f64 / (1 + df) as f64).ln() + 1.0
+    }
+}
+
+/// A result of the query.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hit {
+    pub query: Info,
+    pub info: Info,
+    pub score: f64,
+}
+
+impl Index {
+    /// opens the index file, or returns the empty index if the file does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        match std::fs::File::open(path) {
+            Ok(file) => match serde_json::from_reader::<_, Index>(std::io::BufReader::new(file)) {
+                Ok(mut index) => {
+                    index.rebuild();
+                    Ok(index)
+                },
+                Err(e) => Err(OinkieError::Json(e)),
+            },
+            Err(e) => Err(OinkieError::Io(e)),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match std::fs::File::create(path.as_ref()) {
+            Ok(file) => serde_json::to_writer(std::io::BufWriter::new(file), self)
+                .map_err(OinkieError::Json),
+            Err(e) => Err(OinkieError::Io(e)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &Info> {
+        self.documents.iter().map(|d| &d.info)
+    }
+
+    /// adds the birthmark into the index.
+    /// The stored birthmark with the same name, type, and mode is replaced.
+    pub fn add(&mut self, birthmark: &Birthmark) {
+        self.add_all(std::slice::from_ref(birthmark));
+    }
+
+    /// adds the birthmarks into the index, and rebuilds the index only once.
+    /// The stored birthmarks with the same name, type, and mode are replaced,
+    /// and the last one wins among the given birthmarks of the same name, type, and mode.
+    pub fn add_all(&mut self, birthmarks: &[Birthmark]) {
+        let mut added = HashMap::<&str, Vec<&Info>>::new();
+        let mut documents = vec![];
+        for birthmark in birthmarks.iter().rev() {
+            let infos = added.entry(birthmark.info.name.as_str()).or_default();
+            if !infos.iter().any(|i| i.is_same_type(&birthmark.info)) {
+                infos.push(&birthmark.info);
+                documents.push(Document { info: birthmark.info.clone(), terms: terms(birthmark), group: 0, norm: 0.0 });
+            }
+        }
+        self.documents.retain(|d| !added.get(d.info.name.as_str())
+            .is_some_and(|infos| infos.iter().any(|i| i.is_same_type(&d.info))));
+        self.documents.extend(documents.into_iter().rev());
+        self.rebuild();
+    }
+
+    /// removes the birthmarks of the given name, and returns the number of the removed ones.
+    pub fn remove(&mut self, name: &str) -> usize {
+        let len = self.documents.len();
+        self.documents.retain(|d| d.info.name != name);
+        self.rebuild();
+        len - self.documents.len()
+    }
+
+    /// returns the top-k stored birthmarks of the same type as the query,
+    /// in the descending order of the cosine similarity of their TF-IDF vectors.
+    pub fn query(&self, birthmark: &Birthmark, k: usize) -> Vec<Hit> {
+        let Some(group) = self.groups.iter().position(|g| g.info.is_same_type(&birthmark.info)) else {
+            return vec![];
+        };
+        let query = terms(birthmark);
+        let idfs = query.keys()
+            .map(|term| (term.as_str(), self.groups[group].idf(term)))
+            .collect::<HashMap<_, _>>();
+        let query_norm = query.iter()
+            .map(|(term, tf)| (*tf as f64 * idfs[term.as_str()]).powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        let mut dots = HashMap::<usize, f64>::new();
+        for (term, tf) in &query {
+            let idf = idfs[term.as_str()];
+            let qw = *tf as f64 * idf;
+            for (doc, tf) in self.postings.get(term).into_iter().flatten() {
+                if self.documents[*doc].group == group {
+                    *dots.entry(*doc).or_insert(0.0) += qw * *tf as f64 * idf;
+                }
+            }
+        }
+        let mut hits = dots.into_iter()
+            .map(|(doc, dot)| Hit {
+                query: birthmark.info.clone(),
+                info: self.documents[doc].info.clone(),
+                score: dot / (query_norm * self.documents[doc].norm),
+            })
+            .collect::<Vec<_>>();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.info.name.cmp(&b.info.name)));
+        hits.truncate(k);
+        hits
+    }
+
+    /// rebuilds the postings, the type groups, and the norms of the documents.
+    fn rebuild(&mut self) {
+        self.postings.clear();
+        self.groups.clear();
+        for (doc, d) in self.documents.iter_mut().enumerate() {
+            d.group = match self.groups.iter().position(|g| g.info.is_same_type(&d.info)) {
+                Some(group) => group,
+                None => {
+                    self.groups.push(TypeGroup { info: d.info.clone(), total: 0, df: HashMap::new() });
+                    self.groups.len() - 1
+                },
+            };
+            let group = &mut self.groups[d.group];
+            group.total += 1;
+            for (term, tf) in &d.terms {
+                *group.df.entry(term.clone()).or_insert(0) += 1;
+                self.postings.entry(term.clone()).or_default().push((doc, *tf));
+            }
+        }
+        for d in self.documents.iter_mut() {
+            let group = &self.groups[d.group];
+            d.norm = d.terms.iter()
+                .map(|(term, tf)| (*tf as f64 * group.idf(term)).powi(2))
+                .sum::<f64>()
+                .sqrt();
+        }
+    }
+}
+
+/// returns the term frequencies of the birthmark.
+/// The terms are the string representations of the element keys,
+/// and the frequencies are the weights of the elements.
+fn terms(birthmark: &Birthmark) -> HashMap<String, usize> {
+    let mut terms = HashMap::new();
+    for (key, weight) in birthmark.weights() {
+        *terms.entry(term(&key)).or_insert(0) += weight;
+    }
+    terms
+}
+
+/// returns the string representation of the key, prefixed by the tag of the variant.
+/// The items of the k-grams are joined by the spaces, with the spaces and the backslashes in the items escaped.
+/// [`Element::Freq`] shares the tag with [`Key::Name`], since it is identified by its name.
+fn term(key: &Key<'_>) -> String {
+    match key {
+        Key::Element(Element::Str(s)) => format!("s:{}", s),
+        Key::Element(Element::Int(i)) => format!("i:{}", i),
+        Key::Element(Element::Kgram(items)) => format!("k:{}", items.iter()
+            .map(|item| item.replace('\\', "\\\\").replace(' ', "\\ "))
+            .collect::<Vec<_>>()
+            .join(" ")),
+        Key::Element(Element::Freq(_, name)) => format!("n:{}", name),
+        Key::Name(name) => format!("n:{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::birthmarks::BirthmarkType;
+    use crate::extractors::Mode;
+
+    use super::*;
+
+    fn birthmark(name: &str, elements: &[&str]) -> Birthmark {
+        let info = Info::new(name.into(), PathBuf::from(name), BirthmarkType::OpSeq, Mode::File);
+        Birthmark::new(info, elements.iter().map(|e| Element::Str(e.to_string())).collect())
+    }
+
+    #[test]
+    fn test_term() {
+        let kgram = |items: &[&str]| Element::Kgram(items.iter().map(|i| i.to_string()).collect());
+        let elements = [
+            Element::Str("#5".into()), Element::Int(5),
+            Element::Str("a b".into()), kgram(&["a", "b"]), kgram(&["a b"]), kgram(&["a\\", "b"]),
+            Element::Str("puts".into()), Element::Freq(3, "puts".into()),
+        ];
+        let terms = elements.iter().map(|e| term(&e.key())).collect::<std::collections::HashSet<_>>();
+        assert_eq!(terms.len(), elements.len());
+    }
+
+    #[test]
+    fn test_query() {
+        let mut index = Index::default();
+        index.add(&birthmark("a", &["Add", "Sub", "Call", "Ret"]));
+        index.add(&birthmark("b", &["Load", "Store", "Ret"]));
+        index.add(&birthmark("c", &["Add", "Sub", "Mul", "Ret"]));
+        assert_eq!(index.len(), 3);
+
+        let hits = index.query(&birthmark("q", &["Add", "Sub", "Call", "Ret"]), 2);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].info.name, "a");
+        assert!((hits[0].score - 1.0).abs() < 1e-9);
+        assert_eq!(hits[1].info.name, "c");
+
+        index.add_all(&[birthmark("b", &["Load", "Ret"]), birthmark("d", &["Mul"]), birthmark("b", &["Add", "Ret"])]);
+        assert_eq!(index.list().map(|i| i.name.as_str()).collect::<Vec<_>>(), ["a", "c", "d", "b"]);
+
+        assert_eq!(index.remove("a"), 1);
+        let hits = index.query(&birthmark("q", &["Add", "Sub", "Call", "Ret"]), 2);
+        assert_eq!(hits[0].info.name, "c");
+    }
+}
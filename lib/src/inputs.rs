@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{extractors, OinkieError, Result};
+
+/// The extensions of the files picked up from the directories regardless of their contents.
+const EXTENSIONS: [&str; 2] = ["ll", "bc"];
+
+/// The glob patterns for filtering the input files, and the depth of the checks of the files in the directories.
+/// The patterns without `/` match the file names, and the others match the whole paths.
+/// `*` and `?` do not match `/`, `**` matches any number of the directories, and `[...]` matches a character class.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct Filter {
+    #[clap(long = "include", value_name = "GLOB", help = "Extract only the input files matching the pattern (repeatable)")]
+    pub includes: Vec<Pattern>,
+
+    #[clap(long = "exclude", value_name = "GLOB", help = "Skip the input files matching the pattern (repeatable)")]
+    pub excludes: Vec<Pattern>,
+
+    #[clap(long = "deep-scan", help = "Pick up the object files, the archives, and the compressed files in the directories if they have the bitcode, by reading them entirely")]
+    pub deep_scan: bool,
+}
+
+/// A glob pattern of [`Filter`].
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    matcher: globset::GlobMatcher,
+    whole_path: bool,
+}
+
+impl FromStr for Pattern {
+    type Err = OinkieError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let pattern = s.trim_start_matches("./");
+        match globset::GlobBuilder::new(pattern).literal_separator(true).build() {
+            Ok(glob) => Ok(Pattern { matcher: glob.compile_matcher(), whole_path: pattern.contains('/') }),
+            Err(e) => Err(OinkieError::Format(format!("{}: invalid glob pattern ({})", s, e))),
+        }
+    }
+}
+
+impl Pattern {
+    /// matches the pattern against the file name, or the whole path if the pattern contains `/`.
+    /// The `.` components of the path are ignored.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        if self.whole_path {
+            let path = path.components()
+                .filter(|c| !matches!(c, Component::CurDir))
+                .collect::<PathBuf>();
+            self.matcher.is_match(path)
+        } else {
+            path.file_name().is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+}
+
+impl Filter {
+    pub fn accepts<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        (self.includes.is_empty() || self.includes.iter().any(|p| p.matches(path)))
+            && !self.excludes.iter().any(|p| p.matches(path))
+    }
+}
+
+/// expands the inputs into the files.
+/// The directories are walked recursively in the order of the names for the `.ll` and `.bc` files,
+/// the other files starting with the IR/BC, and the compressed IR/BC files, such as `main.ll.gz`.
+/// The object files with the embedded bitcode and the archives are picked up only with [`Filter::deep_scan`],
+/// and the arguments in the form of `@LISTFILE` are replaced by the paths listed in the file, one per line.
+/// The empty lines and the lines starting with `#` in the list files are ignored.
+/// The resultant files are filtered by the given filter, and the duplicates are removed.
+/// `-`, which stands for the standard input, is kept as is.
+pub fn expand<P: AsRef<Path>>(inputs: &[P], filter: &Filter) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut errs = vec![];
+    for input in inputs {
+        let input = input.as_ref();
+        if is_stdin(input) {
+            files.push(input.to_path_buf());
+            continue;
+        }
+        match input.to_str().and_then(|s| s.strip_prefix('@')) {
+            Some(list) => match read_list(Path::new(list)) {
+                Ok(paths) => paths.iter().for_each(|p| collect(p, filter.deep_scan, &mut files, &mut errs)),
+                Err(e) => errs.push(e),
+            },
+            None => collect(input, filter.deep_scan, &mut files, &mut errs),
+        }
+    }
+    let mut found = HashSet::new();
+    files.retain(|f| (is_stdin(f) || filter.accepts(f)) && found.insert(f.clone()));
+    OinkieError::error_or(files, errs)
+}
+
+pub fn is_stdin<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().as_os_str() == "-"
+}
+
+fn read_list(path: &Path) -> Result<Vec<PathBuf>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(PathBuf::from)
+            .collect()),
+        Err(e) => Err(OinkieError::Io(e)),
+    }
+}
+
+fn collect(path: &Path, deep: bool, files: &mut Vec<PathBuf>, errs: &mut Vec<OinkieError>) {
+    if path.is_dir() {
+        if let Err(e) = walk(path, deep, files) {
+            errs.push(e);
+        }
+    } else if path.exists() {
+        files.push(path.to_path_buf());
+    } else {
+        errs.push(OinkieError::NotFound(path.to_path_buf()));
+    }
+}
+
+/// collects the IR/BC files under the directory, by the extensions or the contents.
+/// The symbolic links to the directories are not followed to avoid the cycles.
+fn walk(dir: &Path, deep: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(OinkieError::Io)?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            walk(&path, deep, files)?;
+        } else if path.is_file() && (path.extension().and_then(|e| e.to_str()).is_some_and(|e| EXTENSIONS.contains(&e))
+            || extractors::has_bitcode(&path, deep)) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let matches = |pattern: &str, path: &str| pattern.parse::<Pattern>().unwrap().matches(path);
+        assert!(matches("*.ll", "build/src/main.ll"));
+        assert!(!matches("*.ll", "build/src/main.bc"));
+        assert!(matches("build/**/*.bc", "./build/src/a/main.bc"));
+        assert!(matches("build/**/*.bc", "build/main.bc"));
+        assert!(!matches("build/*.bc", "build/src/main.bc"));
+        assert!(!matches("build/**/main.bc", "build/xmain.bc"));
+        assert!(matches("**/test/**", "/tmp/test/fib.ll"));
+        assert!(matches("main?.[lb][lc]", "main1.ll"));
+        assert!(!matches("main[!0-9].ll", "main1.ll"));
+        assert!(matches("[]].ll", "].ll"));
+        assert!("[a-".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = std::env::temp_dir().join(format!("oinkie-inputs-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src/test")).unwrap();
+        for file in ["b.ll", "a.bc", "notes.txt", "src/c.ll", "src/test/d.ll"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        std::fs::write(dir.join("5f3a9c"), "source_filename = \"a.c\"\n").unwrap();
+        std::fs::write(dir.join("list.txt"), format!("# inputs\n{}\n\n{}\n", dir.join("b.ll").display(), dir.join("notes.txt").display())).unwrap();
+
+        let filter = Filter { includes: vec![], excludes: vec!["**/test/**".parse().unwrap()], deep_scan: false };
+        let list = format!("@{}", dir.join("list.txt").display());
+        let files = expand(&[dir.clone(), PathBuf::from(list)], &filter).unwrap();
+        let names = files.iter().map(|f| f.strip_prefix(&dir).unwrap().to_path_buf()).collect::<Vec<_>>();
+        assert_eq!(names, ["5f3a9c", "a.bc", "b.ll", "src/c.ll", "notes.txt"].map(PathBuf::from));
+        assert!(expand(&[dir.join("none.ll")], &Filter::default()).is_err());
+        assert_eq!(expand(&["-", "-"], &filter).unwrap(), [PathBuf::from("-")]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,13 @@
 pub mod birthmarks;
 pub mod comparators;
+pub mod composite;
 pub mod extractors;
+pub mod fuzzy;
+mod graphs;
+pub mod index;
+pub mod inputs;
+pub mod minhash;
+pub mod objects;
 
 pub type Result<T> = std::result::Result<T, OinkieError>;
 
@@ -18,6 +25,14 @@ pub enum OinkieError {
     NotImplementedYet(String),
 }
 
+impl std::fmt::Display for OinkieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for OinkieError {}
+
 impl OinkieError {
     pub fn error_or<T>(item: T, err: Vec<OinkieError>) -> Result<T> {
         if err.is_empty() {
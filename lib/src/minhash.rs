@@ -0,0 +1,215 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::birthmarks::{Birthmark, Info};
+use crate::{OinkieError, Result};
+
+/// The MinHash signature of a birthmark, which estimates the Jaccard index of the sets of element keys.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Signature {
+    pub info: Info,
+    pub values: Vec<u64>,
+}
+
+impl Signature {
+    /// returns the estimated Jaccard index, the ratio of the agreed values of two signatures.
+    pub fn estimate(&self, other: &Signature) -> f64 {
+        let len = self.values.len().min(other.values.len());
+        if len == 0 {
+            return 0.0;
+        }
+        let agreed = self.values.iter().zip(&other.values)
+            .filter(|(a, b)| a == b)
+            .count();
+        agreed as f64 / len as f64
+    }
+}
+
+/// generates the MinHash signatures with the given number of hash functions.
+/// The signatures from the hashers with the same number of hashes and the same seed are comparable.
+#[derive(Debug, Clone)]
+pub struct MinHasher {
+    seeds: Vec<u64>,
+}
+
+impl MinHasher {
+    pub fn new(num_hashes: usize, seed: u64) -> Self {
+        let mut state = seed;
+        let seeds = (0..num_hashes).map(|_| {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            mix(state)
+        }).collect();
+        Self { seeds }
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.seeds.len()
+    }
+
+    pub fn signature(&self, birthmark: &Birthmark) -> Signature {
+        let hashes = birthmark.iter()
+            .map(|e| e.key().stable_hash())
+            .collect::<HashSet<_>>();
+        let values = self.seeds.iter()
+            .map(|seed| hashes.iter()
+                .map(|h| mix(h ^ seed))
+                .min()
+                .unwrap_or(u64::MAX))
+            .collect();
+        Signature { info: birthmark.info.clone(), values }
+    }
+}
+
+impl Default for MinHasher {
+    fn default() -> Self {
+        Self::new(128, 0)
+    }
+}
+
+/// The locality sensitive hashing index dividing the signatures into bands.
+/// Two signatures become a candidate pair if all rows of any band agree.
+pub struct LshIndex {
+    rows: usize,
+    buckets: Vec<HashMap<u64, Vec<usize>>>,
+    signatures: Vec<Signature>,
+}
+
+impl LshIndex {
+    pub fn new(bands: usize, rows: usize) -> Self {
+        Self {
+            rows,
+            buckets: vec![HashMap::new(); bands],
+            signatures: vec![],
+        }
+    }
+
+    /// builds the index with the number of bands whose threshold, `(1/b)^(1/r)`,
+    /// is the closest to the given threshold of the Jaccard index.
+    pub fn with_threshold(num_hashes: usize, threshold: f64) -> Result<Self> {
+        bands_for(num_hashes, threshold)
+            .map(|(bands, rows)| Self::new(bands, rows))
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    pub fn signatures(&self) -> &[Signature] {
+        &self.signatures
+    }
+
+    /// inserts the signature, and returns its index.
+    pub fn insert(&mut self, signature: Signature) -> usize {
+        let index = self.signatures.len();
+        for (band, hash) in self.band_hashes(&signature).into_iter().enumerate() {
+            self.buckets[band].entry(hash).or_default().push(index);
+        }
+        self.signatures.push(signature);
+        index
+    }
+
+    /// returns the indexes of the signatures sharing any band with the given signature.
+    pub fn query(&self, signature: &Signature) -> Vec<usize> {
+        let mut result = BTreeSet::new();
+        for (band, hash) in self.band_hashes(signature).into_iter().enumerate() {
+            if let Some(items) = self.buckets[band].get(&hash) {
+                result.extend(items.iter().copied());
+            }
+        }
+        result.into_iter().collect()
+    }
+
+    /// returns the candidate pairs `(i, j)` (`i < j`) of the inserted signatures in the ascending order.
+    pub fn candidates(&self) -> Vec<(usize, usize)> {
+        let mut result = BTreeSet::new();
+        for bucket in self.buckets.iter().flat_map(|b| b.values()) {
+            for (k, &i) in bucket.iter().enumerate() {
+                for &j in &bucket[k + 1..] {
+                    result.insert((i.min(j), i.max(j)));
+                }
+            }
+        }
+        result.into_iter().collect()
+    }
+
+    fn band_hashes(&self, signature: &Signature) -> Vec<u64> {
+        (0..self.buckets.len()).map(|band| {
+            signature.values.iter()
+                .skip(band * self.rows)
+                .take(self.rows)
+                .fold(band as u64, |acc, v| mix(acc ^ v))
+        }).collect()
+    }
+}
+
+/// returns the pair of the number of bands and rows for the given number of hashes and threshold.
+/// The number of hashes must be positive, and the threshold must be in the range of 0.0 to 1.0.
+pub fn bands_for(num_hashes: usize, threshold: f64) -> Result<(usize, usize)> {
+    if num_hashes == 0 {
+        return Err(OinkieError::Format("the number of hashes must be positive".to_string()));
+    }
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(OinkieError::Format(format!("{}: the threshold must be in the range of 0.0 to 1.0", threshold)));
+    }
+    (1..=num_hashes)
+        .filter(|b| num_hashes.is_multiple_of(*b))
+        .map(|b| (b, num_hashes / b))
+        .min_by(|(b1, r1), (b2, r2)| {
+            let d1 = ((1.0 / *b1 as f64).powf(1.0 / *r1 as f64) - threshold).abs();
+            let d2 = ((1.0 / *b2 as f64).powf(1.0 / *r2 as f64) - threshold).abs();
+            d1.total_cmp(&d2)
+        })
+        .ok_or_else(|| OinkieError::Fatal("no bands for the number of hashes".to_string()))
+}
+
+/// splitmix64 finalizer.
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::birthmarks::{BirthmarkType, Element};
+    use crate::extractors::Mode;
+
+    use super::*;
+
+    fn birthmark(name: &str, range: std::ops::Range<i64>) -> Birthmark {
+        let info = Info::new(name.into(), PathBuf::from(name), BirthmarkType::OpSet, Mode::File);
+        Birthmark::new(info, range.map(Element::Int).collect())
+    }
+
+    #[test]
+    fn test_estimate_and_candidates() {
+        let hasher = MinHasher::new(128, 0);
+        let a = hasher.signature(&birthmark("a", 0..100));
+        let b = hasher.signature(&birthmark("b", 10..110));
+        let c = hasher.signature(&birthmark("c", 1000..1100));
+        assert!((a.estimate(&b) - 90.0 / 110.0).abs() < 0.15);
+        assert!(a.estimate(&c) < 0.1);
+
+        let mut index = LshIndex::with_threshold(hasher.num_hashes(), 0.5).unwrap();
+        index.insert(a);
+        index.insert(b);
+        index.insert(c);
+        assert_eq!(index.candidates(), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_bands_for() {
+        assert_eq!(bands_for(128, 0.5).unwrap(), (32, 4));
+        assert_eq!(bands_for(1, 0.8).unwrap(), (1, 1));
+        assert!(bands_for(0, 0.5).is_err());
+        assert!(bands_for(128, 1.5).is_err());
+        assert!(bands_for(128, f64::NAN).is_err());
+    }
+}
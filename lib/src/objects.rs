@@ -0,0 +1,508 @@
+use std::io::Read;
+
+use crate::{OinkieError, Result};
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const BC_MAGIC: [u8; 4] = *b"BC\xc0\xde";
+const WRAPPER_MAGIC: [u8; 4] = [0xde, 0xc0, 0x17, 0x0b];
+const AR_MAGIC: [u8; 8] = *b"!<arch>\n";
+const THIN_AR_MAGIC: [u8; 8] = *b"!<thin>\n";
+const AR_HEADER_LEN: usize = 60;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// The first tokens of the statements at the top level of the textual IR.
+const IR_STATEMENTS: [&str; 11] = [
+    "source_filename", "target ", "define ", "declare ", "attributes ", "module asm", "uselistorder",
+    "@", "%", "!", "$",
+];
+
+/// The section type of the sections occupying no space in the file, such as `.bss`.
+const SHT_NOBITS: u32 = 8;
+
+/// A bitcode module embedded in an object file by `-fembed-bitcode` or `-lto-embed-bitcode`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Embedded {
+    /// the bitcode of the module in the `.llvmbc` section.
+    pub bitcode: Vec<u8>,
+    /// the compiler arguments in the `.llvmcmd` section.
+    /// The linkers concatenate the arguments of the linked objects without the boundaries,
+    /// hence the modules of the linked executables and shared objects share all the arguments.
+    pub command_line: Vec<String>,
+}
+
+/// The upper limit of the size of the decompressed data, against the decompression bombs.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 1 << 30;
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(&ELF_MAGIC)
+}
+
+pub fn is_bitcode(data: &[u8]) -> bool {
+    data.starts_with(&BC_MAGIC) || data.starts_with(&WRAPPER_MAGIC)
+}
+
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&AR_MAGIC) || data.starts_with(&THIN_AR_MAGIC)
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+pub fn is_zstd(data: &[u8]) -> bool {
+    data.starts_with(&ZSTD_MAGIC)
+}
+
+/// guesses whether the data is the textual IR by the first statement except the comments,
+/// such as `source_filename = ...`, `target triple = ...`, `define ...`, and `@g = global ...`.
+/// The data may be truncated, such as the head of a file.
+pub fn is_ir(data: &[u8]) -> bool {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        // the truncated character at the end is not the binary data.
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return false,
+    };
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line.starts_with("; ModuleID") {
+            return true;
+        } else if !line.starts_with(';') {
+            return IR_STATEMENTS.iter().any(|s| line.starts_with(s));
+        }
+    }
+    false
+}
+
+/// decompresses the gzip or zstd compressed data, including the concatenated members or frames,
+/// up to [`MAX_DECOMPRESSED_SIZE`] bytes.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    decompress_with(data, MAX_DECOMPRESSED_SIZE)
+}
+
+/// decompresses the gzip or zstd compressed data, and fails if the decompressed data exceed the limit in bytes.
+pub fn decompress_with(data: &[u8], limit: u64) -> Result<Vec<u8>> {
+    let mut decompressed = vec![];
+    if is_gzip(data) {
+        read_limited(flate2::read::MultiGzDecoder::new(data), limit, &mut decompressed)?;
+    } else if is_zstd(data) {
+        let mut source = data;
+        while !source.is_empty() {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(&mut source)
+                .map_err(|e| OinkieError::Format(format!("Broken zstd data: {}", e)))?;
+            read_limited(decoder, limit, &mut decompressed)?;
+        }
+    } else {
+        return Err(OinkieError::Format("Neither gzip nor zstd data".to_string()));
+    }
+    Ok(decompressed)
+}
+
+/// appends the data of the reader to the buffer, and fails if the buffer exceeds the limit in bytes.
+fn read_limited<R: Read>(reader: R, limit: u64, buffer: &mut Vec<u8>) -> Result<()> {
+    let rest = limit.saturating_sub(buffer.len() as u64);
+    reader.take(rest + 1).read_to_end(buffer).map_err(OinkieError::Io)?;
+    if buffer.len() as u64 > limit {
+        Err(OinkieError::Format(format!("The decompressed data exceed {} bytes", limit)))
+    } else {
+        Ok(())
+    }
+}
+
+/// strips the bitcode wrapper header (`0x0B17C0DE`) used by the Apple platforms,
+/// and returns the wrapped bitcode. The bitcode without the wrapper is returned as is.
+pub fn unwrap_bitcode(data: &[u8]) -> Result<&[u8]> {
+    if !data.starts_with(&WRAPPER_MAGIC) {
+        return Ok(data);
+    }
+    match (u32_le(data, 8), u32_le(data, 12)) {
+        (Some(offset), Some(size)) => data.get(offset as usize..offset as usize + size as usize)
+            .ok_or_else(|| OinkieError::Format("Truncated bitcode wrapper".to_string())),
+        _ => Err(OinkieError::Format("Truncated bitcode wrapper header".to_string())),
+    }
+}
+
+/// A member file of an `ar` archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member<'a> {
+    pub name: String,
+    pub data: &'a [u8],
+}
+
+/// reads the member files of the `ar` archive in the GNU or BSD format, except the symbol tables.
+/// The thin archives, which refer to the external member files, are not supported.
+pub fn read_archive(data: &[u8]) -> Result<Vec<Member<'_>>> {
+    if data.starts_with(&THIN_AR_MAGIC) {
+        return Err(OinkieError::Format("Thin archives are not supported".to_string()));
+    } else if !data.starts_with(&AR_MAGIC) {
+        return Err(OinkieError::Format("Not an ar archive".to_string()));
+    }
+    let mut members = vec![];
+    let mut long_names: &[u8] = &[];
+    let mut pos = AR_MAGIC.len();
+    while pos + AR_HEADER_LEN <= data.len() {
+        let header = &data[pos..pos + AR_HEADER_LEN];
+        if &header[58..60] != b"`\n" {
+            return Err(OinkieError::Format(format!("Malformed ar member header at {}", pos)));
+        }
+        let field = |start: usize, end: usize| String::from_utf8_lossy(&header[start..end]).trim_end().to_string();
+        let size = field(48, 58).parse::<usize>()
+            .map_err(|_| OinkieError::Format(format!("Malformed ar member size at {}", pos)))?;
+        let start = pos + AR_HEADER_LEN;
+        let mut content = start.checked_add(size)
+            .and_then(|end| data.get(start..end))
+            .ok_or_else(|| OinkieError::Format("Truncated ar archive".to_string()))?;
+        pos = start + size + size % 2;
+        let name = field(0, 16);
+        if name == "//" {
+            long_names = content;
+            continue;
+        } else if name == "/" || name == "/SYM64/" || name.starts_with("__.SYMDEF") {
+            continue;
+        }
+        let name = if let Some(len) = name.strip_prefix("#1/").and_then(|l| l.parse::<usize>().ok()) {
+            // BSD: the name of the given length precedes the content.
+            let len = len.min(content.len());
+            let name = String::from_utf8_lossy(&content[..len]).trim_end_matches('\0').to_string();
+            content = &content[len..];
+            name
+        } else if let Some(offset) = name.strip_prefix('/').and_then(|o| o.parse::<usize>().ok()) {
+            // GNU: the offset of the name terminated by `/\n` in the long name table.
+            let name = long_names.get(offset..).unwrap_or(&[]);
+            let name = name.split(|b| *b == b'\n').next().unwrap_or(&[]);
+            String::from_utf8_lossy(name).trim_end_matches('/').to_string()
+        } else {
+            name.strip_suffix('/').unwrap_or(&name).to_string()
+        };
+        members.push(Member { name, data: content });
+    }
+    Ok(members)
+}
+
+/// reads the embedded bitcode modules and the compiler arguments from the ELF object file, shared object, or executable.
+/// The linked executables and shared objects have the modules of all linked objects.
+pub fn read_elf(data: &[u8]) -> Result<Vec<Embedded>> {
+    let elf = Elf::new(data)?;
+    let mut modules = vec![];
+    let mut command_line = vec![];
+    for section in elf.sections()? {
+        match section.name {
+            ".llvmbc" => modules.extend(split_bitcode(section.data)),
+            ".llvmcmd" => command_line.extend(section.data.split(|b| *b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())),
+            _ => (),
+        }
+    }
+    Ok(modules.into_iter()
+        .map(|bitcode| Embedded { bitcode: bitcode.to_vec(), command_line: command_line.clone() })
+        .collect())
+}
+
+/// splits the concatenated bitcode modules, by skipping the top-level blocks of each module.
+/// The padding between the modules is ignored, and the data after the broken module is discarded.
+pub fn split_bitcode(data: &[u8]) -> Vec<&[u8]> {
+    let mut modules = vec![];
+    let mut rest = data;
+    while let Some(len) = bitcode_length(rest) {
+        modules.push(&rest[..len]);
+        rest = &rest[len..];
+        let padding = rest.iter().take_while(|b| **b == 0).count();
+        rest = &rest[padding..];
+    }
+    modules
+}
+
+/// returns the length of the bitcode module at the beginning of the data.
+/// The module is followed by the end of the data, the next module, or the padding.
+fn bitcode_length(data: &[u8]) -> Option<usize> {
+    if data.starts_with(&WRAPPER_MAGIC) {
+        let (offset, size) = (u32_le(data, 8)? as usize, u32_le(data, 12)? as usize);
+        return Some(offset + size).filter(|len| *len <= data.len());
+    } else if !data.starts_with(&BC_MAGIC) {
+        return None;
+    }
+    let mut pos = BC_MAGIC.len();
+    while pos < data.len() && !is_bitcode(&data[pos..]) {
+        let mut bits = Bits { data: &data[pos..], bit: 0 };
+        // ENTER_SUBBLOCK with the abbreviation width 2 at the top level.
+        if bits.read(2)? != 1 {
+            break;
+        }
+        bits.vbr(8)?;
+        bits.vbr(4)?;
+        bits.align32();
+        let words = bits.read(32)? as usize;
+        pos += bits.bit / 8 + words * 4;
+    }
+    Some(pos).filter(|len| *len <= data.len())
+}
+
+fn u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// reads the bitstream in the little-endian order, from the least significant bit.
+struct Bits<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, width: usize) -> Option<u64> {
+        let mut value = 0;
+        for i in 0..width {
+            let bit = self.bit + i;
+            let byte = self.data.get(bit / 8)?;
+            value |= (((byte >> (bit % 8)) & 1) as u64) << i;
+        }
+        self.bit += width;
+        Some(value)
+    }
+
+    fn vbr(&mut self, width: usize) -> Option<u64> {
+        let flag = 1 << (width - 1);
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let chunk = self.read(width)?;
+            value |= (chunk & (flag - 1)) << shift;
+            if chunk & flag == 0 {
+                return Some(value);
+            }
+            shift += width - 1;
+            if shift >= 64 {
+                return None;
+            }
+        }
+    }
+
+    fn align32(&mut self) {
+        self.bit = self.bit.div_ceil(32) * 32;
+    }
+}
+
+struct Section<'a> {
+    name: &'a str,
+    data: &'a [u8],
+}
+
+/// reads the section headers of the 32/64-bit and little/big-endian ELF files.
+struct Elf<'a> {
+    data: &'a [u8],
+    is64: bool,
+    little: bool,
+}
+
+impl<'a> Elf<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        if !is_elf(data) || data.len() < 6 {
+            return Err(OinkieError::Format("Not an ELF file".to_string()));
+        }
+        match (data[4], data[5]) {
+            (class @ (1 | 2), order @ (1 | 2)) => Ok(Self { data, is64: class == 2, little: order == 1 }),
+            _ => Err(OinkieError::Format("Unknown ELF class or byte order".to_string())),
+        }
+    }
+
+    fn uint(&self, offset: usize, size: usize) -> Result<u64> {
+        let bytes = offset.checked_add(size)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| OinkieError::Format("Truncated ELF file".to_string()))?;
+        let fold = |v: u64, b: &u8| (v << 8) | *b as u64;
+        Ok(if self.little { bytes.iter().rev().fold(0, fold) } else { bytes.iter().fold(0, fold) })
+    }
+
+    /// reads the word of the class, 4 bytes for ELF32 and 8 bytes for ELF64.
+    fn word(&self, offset: usize) -> Result<u64> {
+        self.uint(offset, if self.is64 { 8 } else { 4 })
+    }
+
+    fn sections(&self) -> Result<Vec<Section<'a>>> {
+        let (shoff, shentsize, shnum, shstrndx) = if self.is64 {
+            (self.word(0x28)?, self.uint(0x3a, 2)?, self.uint(0x3c, 2)?, self.uint(0x3e, 2)?)
+        } else {
+            (self.word(0x20)?, self.uint(0x2e, 2)?, self.uint(0x30, 2)?, self.uint(0x32, 2)?)
+        };
+        if shoff == 0 {
+            return Ok(vec![]);
+        } else if shoff as usize > self.data.len() {
+            return Err(OinkieError::Format("Truncated ELF file".to_string()));
+        }
+        let (shoff, shentsize) = (shoff as usize, shentsize as usize);
+        if shentsize < if self.is64 { 64 } else { 40 } {
+            return Err(OinkieError::Format(format!("Invalid ELF section header size: {shentsize}")));
+        }
+        let header = |i: usize| shoff + i * shentsize;
+        // the numbers exceeding the fields are stored in the first section header.
+        let shnum = if shnum == 0 { self.section_size(header(0))? } else { shnum };
+        let shstrndx = if shstrndx == 0xffff { self.uint(header(0) + if self.is64 { 40 } else { 24 }, 4)? } else { shstrndx };
+        if shnum > ((self.data.len() - shoff) / shentsize) as u64 || shstrndx >= shnum {
+            return Err(OinkieError::Format("Truncated ELF file".to_string()));
+        }
+        let names = self.section_data(header(shstrndx as usize))?;
+        let mut sections = vec![];
+        for i in 0..shnum as usize {
+            let name = self.uint(header(i), 4)? as usize;
+            let name = names.get(name..)
+                .and_then(|n| n.split(|b| *b == 0).next())
+                .and_then(|n| std::str::from_utf8(n).ok())
+                .unwrap_or("");
+            sections.push(Section { name, data: self.section_data(header(i))? });
+        }
+        Ok(sections)
+    }
+
+    fn section_size(&self, header: usize) -> Result<u64> {
+        self.word(header + if self.is64 { 32 } else { 20 })
+    }
+
+    fn section_data(&self, header: usize) -> Result<&'a [u8]> {
+        if self.uint(header + 4, 4)? as u32 == SHT_NOBITS {
+            return Ok(&[]);
+        }
+        let offset = self.word(header + if self.is64 { 24 } else { 16 })? as usize;
+        let size = self.section_size(header)? as usize;
+        offset.checked_add(size)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| OinkieError::Format("Truncated ELF section".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a bitcode module with a single empty block of the given id.
+    fn module(id: u8) -> Vec<u8> {
+        let header = 1 | (id as u32) << 2 | 5 << 10;
+        [BC_MAGIC.to_vec(), header.to_le_bytes().to_vec(), 1u32.to_le_bytes().to_vec(), vec![0; 4]].concat()
+    }
+
+    /// builds a little-endian ELF64 file with the given sections.
+    fn elf(sections: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut names = vec![0u8];
+        let mut data = vec![0u8; 64];
+        let mut headers = vec![0u8; 64];
+        for (name, content) in sections.iter().chain([(".shstrtab", vec![])].iter()) {
+            let name_offset = names.len() as u32;
+            names.extend(name.bytes().chain([0]));
+            let content = if *name == ".shstrtab" { names.clone() } else { content.clone() };
+            let mut header = vec![0u8; 64];
+            header[0..4].copy_from_slice(&name_offset.to_le_bytes());
+            header[4..8].copy_from_slice(&1u32.to_le_bytes());
+            header[24..32].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(content.len() as u64).to_le_bytes());
+            data.extend(content);
+            headers.extend(header);
+        }
+        let shoff = data.len() as u64;
+        data[0..6].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1]);
+        data[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        data[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        data[0x3c..0x3e].copy_from_slice(&(sections.len() as u16 + 2).to_le_bytes());
+        data[0x3e..0x40].copy_from_slice(&(sections.len() as u16 + 1).to_le_bytes());
+        data.extend(headers);
+        data
+    }
+
+    #[test]
+    fn test_split_bitcode() {
+        let data = [module(13), vec![0; 8], module(8), vec![0xff; 3]].concat();
+        assert_eq!(split_bitcode(&data), [&module(13)[..], &module(8)[..]]);
+        assert!(split_bitcode(b"BC").is_empty());
+        assert!(split_bitcode(b"\x7fELF").is_empty());
+    }
+
+    /// builds an ar archive of the given members with the raw names in the headers.
+    fn archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = AR_MAGIC.to_vec();
+        for (name, content) in members {
+            data.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 644, content.len()).bytes());
+            data.extend(*content);
+            if content.len() % 2 == 1 {
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_archive() {
+        let bc = module(8);
+        let data = archive(&[
+            ("/", b"\0\0\0\0"),
+            ("//", b"a_very_long_member_name.o/\n"),
+            ("short.o/", b"odd"),
+            ("/0", &bc),
+            ("#1/12", b"bsd_name.o\0\0BC"),
+        ]);
+        let members = read_archive(&data).unwrap();
+        assert_eq!(members, [
+            Member { name: "short.o".into(), data: b"odd" },
+            Member { name: "a_very_long_member_name.o".into(), data: &bc },
+            Member { name: "bsd_name.o".into(), data: b"BC" },
+        ]);
+        assert!(read_archive(&data[..data.len() - 1]).is_err());
+        assert!(read_archive(b"!<thin>\n").is_err());
+    }
+
+    #[test]
+    fn test_unwrap_bitcode() {
+        let bc = module(8);
+        let wrapper = [&WRAPPER_MAGIC[..], &0u32.to_le_bytes(), &20u32.to_le_bytes(), &(bc.len() as u32).to_le_bytes(), &7u32.to_le_bytes(), &bc, &[0; 4]].concat();
+        assert_eq!(unwrap_bitcode(&wrapper).unwrap(), &bc[..]);
+        assert_eq!(unwrap_bitcode(&bc).unwrap(), &bc[..]);
+        assert_eq!(split_bitcode(&wrapper), [&wrapper[..20 + bc.len()]]);
+        assert!(unwrap_bitcode(&wrapper[..24]).is_err());
+    }
+
+    #[test]
+    fn test_is_ir() {
+        assert!(is_ir(b"; ModuleID = 'main.c'\nsource_filename = \"main.c\"\n"));
+        assert!(is_ir(b"\n; comment\n  define i32 @main() {\n"));
+        assert!(is_ir(b"@.str = private constant [3 x i8] c\"hi\\00\"\n"));
+        assert!(is_ir("target datalayout = \"e\" ; \u{3042}".as_bytes().split_last().unwrap().1));
+        assert!(!is_ir(b"int main() { return 0; }\n"));
+        assert!(!is_ir(b"; only comments\n"));
+        assert!(!is_ir(&module(8)));
+    }
+
+    #[test]
+    fn test_decompress() {
+        use std::io::Write;
+        let ir = b"source_filename = \"a.c\"\n";
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(ir).unwrap();
+        let gzip = encoder.finish().unwrap();
+        assert!(is_gzip(&gzip));
+        assert_eq!(decompress(&[gzip.clone(), gzip].concat()).unwrap(), [&ir[..], &ir[..]].concat());
+        // a zstd frame of the raw block with the single segment and the content size.
+        let zstd = [&ZSTD_MAGIC[..], &[0x20, ir.len() as u8], &[(ir.len() as u8) << 3 | 1, 0, 0], ir].concat();
+        assert_eq!(decompress(&zstd).unwrap(), ir);
+        assert!(decompress(ir).is_err());
+        assert_eq!(decompress_with(&zstd, ir.len() as u64).unwrap(), ir);
+        assert!(decompress_with(&zstd, ir.len() as u64 - 1).is_err());
+        assert!(decompress_with(&[zstd.clone(), zstd].concat(), ir.len() as u64 + 1).is_err());
+    }
+
+    #[test]
+    fn test_read_elf() {
+        let data = elf(&[
+            (".text", vec![0x90; 16]),
+            (".llvmbc", [module(13), module(8)].concat()),
+            (".llvmcmd", b"-triple\0x86_64-unknown-linux-gnu\0-O2\0".to_vec()),
+        ]);
+        let embedded = read_elf(&data).unwrap();
+        assert_eq!(embedded.iter().map(|e| e.bitcode.clone()).collect::<Vec<_>>(), [module(13), module(8)]);
+        assert!(embedded.iter().all(|e| e.command_line == ["-triple", "x86_64-unknown-linux-gnu", "-O2"]));
+        assert!(read_elf(&data[..0x30]).is_err());
+
+        let mut malformed = data.clone();
+        malformed[0x3a..0x3c].copy_from_slice(&8u16.to_le_bytes());
+        assert!(read_elf(&malformed).is_err());
+        let mut malformed = data.clone();
+        malformed[0x3c..0x3e].copy_from_slice(&0xfff0u16.to_le_bytes());
+        assert!(read_elf(&malformed).is_err());
+    }
+}